@@ -0,0 +1,340 @@
+use std::fmt;
+use std::io;
+use std::io::Write;
+
+use aes::{Aes128, Aes256};
+use chacha20::ChaCha20;
+use ctr::cipher::{KeyIvInit, StreamCipher, StreamCipherSeek};
+use ctr::Ctr128BE;
+
+use crate::fs::PosRead;
+
+// Maximum chunk of input encrypted per `EncryptingWriter::write`.
+const WRITE_CHUNK: usize = 8 * 1024;
+
+/// A seekable stream cipher, supporting application of the keystream at any
+/// byte offset, without processing any prior bytes of the stream.
+///
+/// Instances are constructed with key and IV (or nonce) for one of the
+/// supported algorithms. Since applying the keystream is symmetric, the same
+/// instance type is used for both encryption and decryption.
+///
+/// Note that no authentication is provided: ciphertext modifications are not
+/// detected. The user is responsible for never re-using the same key and IV
+/// for different content.
+#[derive(Clone)]
+pub struct SeekableCipher {
+    inner: Inner,
+}
+
+#[derive(Clone)]
+enum Inner {
+    Aes128Ctr(Box<Ctr128BE<Aes128>>),
+    Aes256Ctr(Box<Ctr128BE<Aes256>>),
+    // ChaCha20 is not `Clone`, but is inexpensive to initialize
+    ChaCha20 { key: [u8; 32], nonce: [u8; 12] },
+}
+
+impl SeekableCipher {
+    /// New AES-128 instance in CTR mode, with a 128-bit big endian counter
+    /// initialized from the IV.
+    pub fn aes128_ctr(key: &[u8; 16], iv: &[u8; 16]) -> SeekableCipher {
+        let c = Ctr128BE::<Aes128>::new(key.into(), iv.into());
+        SeekableCipher { inner: Inner::Aes128Ctr(Box::new(c)) }
+    }
+
+    /// New AES-256 instance in CTR mode, with a 128-bit big endian counter
+    /// initialized from the IV.
+    pub fn aes256_ctr(key: &[u8; 32], iv: &[u8; 16]) -> SeekableCipher {
+        let c = Ctr128BE::<Aes256>::new(key.into(), iv.into());
+        SeekableCipher { inner: Inner::Aes256Ctr(Box::new(c)) }
+    }
+
+    /// New ChaCha20 (IETF, RFC 8439) instance. With the 32-bit block
+    /// counter, a stream is limited to 256 GiB.
+    pub fn chacha20(key: &[u8; 32], nonce: &[u8; 12]) -> SeekableCipher {
+        SeekableCipher {
+            inner: Inner::ChaCha20 { key: *key, nonce: *nonce }
+        }
+    }
+
+    /// Apply (XOR) the keystream, starting at the given byte offset of the
+    /// stream, to the buffer in place. Returns an error if the offset and
+    /// length exceed the maximum length of the stream.
+    pub fn apply_keystream_at(&self, buf: &mut [u8], offset: u64)
+        -> io::Result<()>
+    {
+        match self.inner {
+            Inner::Aes128Ctr(ref c) => apply_at(*c.clone(), buf, offset),
+            Inner::Aes256Ctr(ref c) => apply_at(*c.clone(), buf, offset),
+            Inner::ChaCha20 { ref key, ref nonce } => {
+                let c = ChaCha20::new(key.into(), nonce.into());
+                apply_at(c, buf, offset)
+            }
+        }
+    }
+}
+
+impl fmt::Debug for SeekableCipher {
+    // Keys are not printed
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self.inner {
+            Inner::Aes128Ctr(_) => "Aes128Ctr",
+            Inner::Aes256Ctr(_) => "Aes256Ctr",
+            Inner::ChaCha20 { .. } => "ChaCha20",
+        };
+        f.debug_struct("SeekableCipher").field("algorithm", &name).finish()
+    }
+}
+
+fn apply_at<C>(mut c: C, buf: &mut [u8], offset: u64) -> io::Result<()>
+    where C: StreamCipher + StreamCipherSeek
+{
+    c.try_seek(offset)
+        .and_then(|_| c.try_apply_keystream(buf))
+        .map_err(|_| io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "offset exceeds maximum cipher stream length"
+        ))
+}
+
+/// Wraps any `PosRead` of encrypted content, decrypting on each positioned
+/// read via a [`SeekableCipher`] keystream at the same offset.
+///
+/// The offsets of the inner `PosRead` are the offsets of the cipher stream,
+/// so wrap a `ReadSlice` if the encrypted content starts elsewhere than the
+/// start of a file. The result may in turn be used with `ReadPos` or
+/// `ReadSlice` to read arbitrary ranges (e.g. via `subslice`) without
+/// decrypting from the start.
+///
+/// ## Example
+///
+/// ``` rust
+/// # use std::io;
+/// use std::io::{Read, Write};
+/// use olio::crypto::{DecryptingPosRead, EncryptingWriter, SeekableCipher};
+/// use olio::fs::ReadSlice;
+/// use tempfile::tempfile;
+///
+/// # fn run() -> Result<(), io::Error> {
+/// let cipher = SeekableCipher::chacha20(&[7u8; 32], &[1u8; 12]);
+///
+/// let mut w = EncryptingWriter::new(tempfile()?, cipher.clone());
+/// w.write_all(b"0123456789")?;
+/// let file = w.into_inner();
+///
+/// let rslice = ReadSlice::new(DecryptingPosRead::new(&file, cipher), 0, 10);
+/// let mut buf = Vec::new();
+/// rslice.subslice(2, 7).read_to_end(&mut buf)?;
+/// assert_eq!(&buf, b"23456");
+/// # Ok(())
+/// # }
+/// # run().unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct DecryptingPosRead<P>
+    where P: PosRead
+{
+    pos_read: P,
+    cipher: SeekableCipher,
+}
+
+impl<P> DecryptingPosRead<P>
+    where P: PosRead
+{
+    /// New instance wrapping a `PosRead` of encrypted content.
+    pub fn new(pos_read: P, cipher: SeekableCipher) -> Self {
+        DecryptingPosRead { pos_read, cipher }
+    }
+
+    /// Return a reference to the inner `PosRead`.
+    pub fn get_ref(&self) -> &P {
+        &self.pos_read
+    }
+
+    /// Unwrap and return the inner `PosRead`.
+    pub fn into_inner(self) -> P {
+        self.pos_read
+    }
+}
+
+impl<P> PosRead for DecryptingPosRead<P>
+    where P: PosRead
+{
+    fn pread(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        let len = self.pos_read.pread(buf, offset)?;
+        self.cipher.apply_keystream_at(&mut buf[..len], offset)?;
+        Ok(len)
+    }
+}
+
+/// Wraps any `Write`, encrypting all bytes written via a
+/// [`SeekableCipher`] keystream, for content readable via
+/// [`DecryptingPosRead`].
+///
+/// The stream offset starts at 0, or the offset given via
+/// [`EncryptingWriter::with_offset`] (e.g. for appending), and is advanced
+/// by the number of bytes accepted by the inner writer, so partial writes
+/// are handled correctly.
+#[derive(Debug)]
+pub struct EncryptingWriter<W>
+    where W: Write
+{
+    inner: W,
+    cipher: SeekableCipher,
+    pos: u64,
+    buf: Vec<u8>,
+}
+
+impl<W> EncryptingWriter<W>
+    where W: Write
+{
+    /// New instance wrapping a `Write`, starting at stream offset 0.
+    pub fn new(inner: W, cipher: SeekableCipher) -> Self {
+        EncryptingWriter::with_offset(inner, cipher, 0)
+    }
+
+    /// New instance wrapping a `Write`, starting at the given stream
+    /// offset.
+    pub fn with_offset(inner: W, cipher: SeekableCipher, offset: u64)
+        -> Self
+    {
+        EncryptingWriter { inner, cipher, pos: offset, buf: Vec::new() }
+    }
+
+    /// Return the current stream offset.
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// Return a reference to the inner `Write`.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Unwrap and return the inner `Write`. No buffered bytes are lost, as
+    /// this type does no buffering across writes.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W> Write for EncryptingWriter<W>
+    where W: Write
+{
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let data = &data[..data.len().min(WRITE_CHUNK)];
+        self.buf.clear();
+        self.buf.extend_from_slice(data);
+        self.cipher.apply_keystream_at(&mut self.buf, self.pos)?;
+        let len = self.inner.write(&self.buf)?;
+        self.pos += len as u64;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::sync::Arc;
+
+    use tempfile::tempfile;
+
+    use super::*;
+    use crate::fs::{ReadPos, ReadSlice};
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len()).step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i+2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_aes128_ctr_vector() {
+        // NIST SP 800-38A, F.5.1 CTR-AES128.Encrypt, block 1 and 2
+        let mut key = [0u8; 16];
+        key.copy_from_slice(&hex("2b7e151628aed2a6abf7158809cf4f3c"));
+        let mut iv = [0u8; 16];
+        iv.copy_from_slice(&hex("f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff"));
+        let cipher = SeekableCipher::aes128_ctr(&key, &iv);
+
+        let mut buf = hex("ae2d8a571e03ac9c9eb76fac45af8e51");
+        cipher.apply_keystream_at(&mut buf, 16).unwrap();
+        assert_eq!(buf, hex("9806f66b7970fdff8617187bb9fffdff"));
+
+        let mut buf = hex("6bc1bee22e409f96e93d7e117393172a");
+        cipher.apply_keystream_at(&mut buf, 0).unwrap();
+        assert_eq!(buf, hex("874d6191b620e3261bef6864990db6ce"));
+    }
+
+    fn ciphers() -> Vec<SeekableCipher> {
+        vec![
+            SeekableCipher::aes128_ctr(&[1u8; 16], &[2u8; 16]),
+            SeekableCipher::aes256_ctr(&[3u8; 32], &[4u8; 16]),
+            SeekableCipher::chacha20(&[5u8; 32], &[6u8; 12]),
+        ]
+    }
+
+    fn plain(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_round_trip_ranges() {
+        let text = plain(20_000);
+        for cipher in ciphers() {
+            let mut w = EncryptingWriter::new(tempfile().unwrap(),
+                                              cipher.clone());
+            w.write_all(&text).unwrap();
+            assert_eq!(w.position(), 20_000);
+            let f = Arc::new(w.into_inner());
+
+            let mut raw = Vec::new();
+            ReadPos::new(f.clone(), 20_000).read_to_end(&mut raw).unwrap();
+            assert_ne!(raw, text);
+
+            let r = ReadSlice::new(DecryptingPosRead::new(f, cipher),
+                                   0, 20_000);
+            for &(s, e) in &[(0, 20_000), (3, 17), (63, 65), (9_999, 19_001)]
+            {
+                let mut buf = Vec::new();
+                r.subslice(s, e).read_to_end(&mut buf).unwrap();
+                assert_eq!(&buf[..], &text[(s as usize)..(e as usize)]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_append_with_offset() {
+        let text = plain(100);
+        let cipher = SeekableCipher::aes256_ctr(&[9u8; 32], &[8u8; 16]);
+        let mut w = EncryptingWriter::new(Vec::new(), cipher.clone());
+        w.write_all(&text[..33]).unwrap();
+        let out = w.into_inner();
+        let mut w = EncryptingWriter::with_offset(out, cipher.clone(), 33);
+        w.write_all(&text[33..]).unwrap();
+        let mut out = w.into_inner();
+
+        cipher.apply_keystream_at(&mut out, 0).unwrap();
+        assert_eq!(out, text);
+    }
+
+    #[test]
+    fn test_chacha20_limit() {
+        let cipher = SeekableCipher::chacha20(&[5u8; 32], &[6u8; 12]);
+        let mut buf = [0u8; 1];
+        let e = cipher.apply_keystream_at(&mut buf, 1 << 40).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_debug_no_key() {
+        let cipher = SeekableCipher::aes128_ctr(&[1u8; 16], &[2u8; 16]);
+        assert_eq!(format!("{:?}", cipher),
+                   "SeekableCipher { algorithm: \"Aes128Ctr\" }");
+    }
+}
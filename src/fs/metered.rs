@@ -0,0 +1,394 @@
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering::Relaxed};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::fs::PosRead;
+
+// Latency buckets are by bit length of the nanosecond duration, so bucket 0
+// is 0ns, bucket `i` is [2^(i-1), 2^i) ns, and the last bucket is open
+// ended, from 2^30 ns (~1.07s).
+const LATENCY_BUCKETS: usize = 32;
+
+/// Wraps any `PosRead`, recording positioned read metrics to a shared
+/// [`ReadMetrics`] instance.
+///
+/// The metrics are held via `Arc`, so all clones of this wrapper, and thus
+/// all `ReadPos` or `ReadSlice` instances derived (e.g. via `subslice`) from
+/// the same source, record to the same metrics. Independent sources may also
+/// share metrics via [`MeteredPosRead::with_metrics`].
+///
+/// ## Example
+///
+/// ``` rust
+/// # use std::io;
+/// use std::io::{Read, Write};
+/// use olio::fs::{MeteredPosRead, ReadSlice};
+/// use tempfile::tempfile;
+///
+/// # fn run() -> Result<(), io::Error> {
+/// let mut file = tempfile()?;
+/// file.write_all(b"0123456789")?;
+///
+/// let mp = MeteredPosRead::new(&file);
+/// let metrics = mp.metrics().clone();
+/// let rslice = ReadSlice::new(mp, 0, 10);
+///
+/// let mut buf = Vec::new();
+/// rslice.subslice(2, 7).read_to_end(&mut buf)?;
+/// assert_eq!(&buf, b"23456");
+///
+/// let snap = metrics.snapshot();
+/// assert_eq!(snap.bytes(), 5);
+/// assert_eq!(snap.latency().count(), snap.reads());
+/// # Ok(())
+/// # }
+/// # run().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct MeteredPosRead<P>
+    where P: PosRead
+{
+    pos_read: P,
+    metrics: Arc<ReadMetrics>,
+}
+
+impl<P> MeteredPosRead<P>
+    where P: PosRead
+{
+    /// New instance wrapping a `PosRead`, with new, zeroed metrics.
+    pub fn new(pos_read: P) -> Self {
+        MeteredPosRead::with_metrics(pos_read, Arc::new(ReadMetrics::new()))
+    }
+
+    /// New instance wrapping a `PosRead`, recording to the given (possibly
+    /// shared) metrics.
+    pub fn with_metrics(pos_read: P, metrics: Arc<ReadMetrics>) -> Self {
+        MeteredPosRead { pos_read, metrics }
+    }
+
+    /// Return a reference to the shared metrics.
+    pub fn metrics(&self) -> &Arc<ReadMetrics> {
+        &self.metrics
+    }
+
+    /// Return a reference to the inner `PosRead`.
+    pub fn get_ref(&self) -> &P {
+        &self.pos_read
+    }
+
+    /// Unwrap and return the inner `PosRead`.
+    pub fn into_inner(self) -> P {
+        self.pos_read
+    }
+}
+
+impl<P> Clone for MeteredPosRead<P>
+    where P: PosRead + Clone
+{
+    /// Return a new instance by clone of the inner `PosRead`, recording to
+    /// the same shared metrics as self.
+    fn clone(&self) -> Self {
+        MeteredPosRead { pos_read: self.pos_read.clone(),
+                         metrics: self.metrics.clone() }
+    }
+}
+
+impl<P> PosRead for MeteredPosRead<P>
+    where P: PosRead
+{
+    fn pread(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        let start = Instant::now();
+        let res = self.pos_read.pread(buf, offset);
+        self.metrics.record(&res, buf.len(), start.elapsed());
+        res
+    }
+}
+
+/// Shared, concurrently updated metrics of positioned reads.
+///
+/// All counters are individual atomic integers, with minimal overhead per
+/// read. A [`ReadSnapshot`] reads each counter atomically, but is not
+/// guaranteed to be consistent across counters with concurrent reads in
+/// progress.
+#[derive(Debug, Default)]
+pub struct ReadMetrics {
+    reads: AtomicU64,
+    bytes: AtomicU64,
+    short_reads: AtomicU64,
+    errors: AtomicU64,
+    latency: [AtomicU64; LATENCY_BUCKETS],
+}
+
+impl ReadMetrics {
+    /// New instance with all counters zeroed.
+    pub fn new() -> ReadMetrics {
+        ReadMetrics::default()
+    }
+
+    /// Return a snapshot of current values.
+    pub fn snapshot(&self) -> ReadSnapshot {
+        self.collect(|c| c.load(Relaxed))
+    }
+
+    /// Reset all counters to zero, returning a snapshot of the values prior
+    /// to reset. Each counter is swapped atomically, so no concurrently
+    /// recorded read is lost between successive calls.
+    pub fn reset(&self) -> ReadSnapshot {
+        self.collect(|c| c.swap(0, Relaxed))
+    }
+
+    fn collect<F>(&self, f: F) -> ReadSnapshot
+        where F: Fn(&AtomicU64) -> u64
+    {
+        let mut counts = [0u64; LATENCY_BUCKETS];
+        for (o, c) in counts.iter_mut().zip(self.latency.iter()) {
+            *o = f(c);
+        }
+        ReadSnapshot {
+            reads: f(&self.reads),
+            bytes: f(&self.bytes),
+            short_reads: f(&self.short_reads),
+            errors: f(&self.errors),
+            latency: LatencyHistogram { counts },
+        }
+    }
+
+    fn record(&self,
+              res: &io::Result<usize>,
+              requested: usize,
+              elapsed: Duration)
+    {
+        // Relaxed is sufficient: counters are independent and only summed.
+        self.reads.fetch_add(1, Relaxed);
+        match *res {
+            Ok(len) => {
+                self.bytes.fetch_add(len as u64, Relaxed);
+                if len < requested {
+                    self.short_reads.fetch_add(1, Relaxed);
+                }
+            }
+            Err(_) => {
+                self.errors.fetch_add(1, Relaxed);
+            }
+        }
+        self.latency[latency_bucket(elapsed)].fetch_add(1, Relaxed);
+    }
+}
+
+/// A point-in-time copy of [`ReadMetrics`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReadSnapshot {
+    reads: u64,
+    bytes: u64,
+    short_reads: u64,
+    errors: u64,
+    latency: LatencyHistogram,
+}
+
+impl ReadSnapshot {
+    /// Return the number of `pread` operations, including errors.
+    pub fn reads(&self) -> u64 {
+        self.reads
+    }
+
+    /// Return the total number of bytes read.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Return the number of successful reads returning fewer bytes than
+    /// requested. This includes 0 length reads at or beyond the end.
+    pub fn short_reads(&self) -> u64 {
+        self.short_reads
+    }
+
+    /// Return the number of reads returning an error.
+    pub fn errors(&self) -> u64 {
+        self.errors
+    }
+
+    /// Return the histogram of read latencies.
+    pub fn latency(&self) -> &LatencyHistogram {
+        &self.latency
+    }
+}
+
+/// Histogram of read latencies, with exponential (power of 2 nanosecond)
+/// buckets.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    counts: [u64; LATENCY_BUCKETS],
+}
+
+impl LatencyHistogram {
+    /// Return the total count of recorded latencies.
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Return the count of each bucket, in ascending latency order. Bucket
+    /// `i` contains latencies less than [`LatencyHistogram::upper_bound`]
+    /// `(i)` and not included in any prior bucket.
+    pub fn buckets(&self) -> &[u64] {
+        &self.counts
+    }
+
+    /// Return the exclusive upper bound of latencies in bucket `i`, or `None`
+    /// for the last, open ended bucket. This _panics_ if `i` is out of range.
+    pub fn upper_bound(&self, i: usize) -> Option<Duration> {
+        assert!(i < LATENCY_BUCKETS);
+        if i < LATENCY_BUCKETS - 1 {
+            Some(Duration::from_nanos(1u64 << i))
+        } else {
+            None
+        }
+    }
+
+    /// Return an estimate of the latency at the given quantile (0.0 to 1.0)
+    /// as the upper bound of the bucket containing it, or `None` if empty or
+    /// if contained by the last, open ended bucket.
+    pub fn quantile(&self, q: f64) -> Option<Duration> {
+        let total = self.count();
+        if total == 0 {
+            return None;
+        }
+        let q = q.max(0.0).min(1.0);
+        let rank = ((total as f64 * q).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (i, c) in self.counts.iter().enumerate() {
+            seen += c;
+            if seen >= rank {
+                return self.upper_bound(i);
+            }
+        }
+        None
+    }
+}
+
+// Return latency bucket index for the given elapsed duration.
+fn latency_bucket(elapsed: Duration) -> usize {
+    let nanos = elapsed.as_secs()
+        .saturating_mul(1_000_000_000)
+        .saturating_add(u64::from(elapsed.subsec_nanos()));
+    let bits = (64 - nanos.leading_zeros()) as usize;
+    bits.min(LATENCY_BUCKETS - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::sync::Arc;
+    use std::thread;
+
+    use tempfile::tempfile;
+
+    use super::*;
+    use crate::fs::{ReadPos, ReadSlice};
+
+    #[test]
+    fn test_subslice_shared() {
+        let mut f = tempfile().unwrap();
+        f.write_all(b"0123456789").unwrap();
+
+        let mp = MeteredPosRead::new(Arc::new(f));
+        let metrics = mp.metrics().clone();
+        let r0 = ReadSlice::new(mp, 0, 10);
+        let mut r1 = r0.subslice(2, 7);
+        let mut r2 = r0.clone();
+
+        let mut buf = [0u8; 5];
+        r1.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"23456");
+        assert_eq!(r1.read(&mut buf).unwrap(), 0);
+        let mut buf = [0u8; 4];
+        r2.read_exact(&mut buf).unwrap();
+
+        let snap = metrics.snapshot();
+        assert_eq!(snap.reads(), 2); // read at end of slice is not a pread
+        assert_eq!(snap.bytes(), 9);
+        assert_eq!(snap.short_reads(), 0);
+        assert_eq!(snap.errors(), 0);
+        assert_eq!(snap.latency().count(), 2);
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut f = tempfile().unwrap();
+        f.write_all(b"0123456789").unwrap();
+
+        let mp = MeteredPosRead::new(f);
+        let metrics = mp.metrics().clone();
+        let mut r = ReadPos::new(mp, 10);
+        let mut buf = [0u8; 5];
+        r.read_exact(&mut buf).unwrap();
+        let mut buf = [0u8; 8];
+        assert_eq!(r.read(&mut buf).unwrap(), 5);
+
+        let prior = metrics.reset();
+        assert_eq!(prior.reads(), 2);
+        assert_eq!(prior.bytes(), 10);
+        assert_eq!(prior.short_reads(), 1);
+        assert_eq!(metrics.snapshot(), ReadSnapshot::default());
+    }
+
+    struct FailRead;
+
+    impl PosRead for FailRead {
+        fn pread(&self, _buf: &mut [u8], _offset: u64) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::Other, "fail"))
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let mp = MeteredPosRead::new(FailRead);
+        let mut buf = [0u8; 5];
+        assert!(mp.pread(&mut buf, 0).is_err());
+        let snap = mp.metrics().snapshot();
+        assert_eq!(snap.reads(), 1);
+        assert_eq!(snap.errors(), 1);
+        assert_eq!(snap.bytes(), 0);
+        assert_eq!(snap.short_reads(), 0);
+    }
+
+    #[test]
+    fn test_concurrent() {
+        let mut f = tempfile().unwrap();
+        f.write_all(b"0123456789").unwrap();
+        let mp = MeteredPosRead::new(Arc::new(f));
+
+        let mut threads = Vec::with_capacity(20);
+        for _ in 0..20 {
+            let mut r = ReadPos::new(mp.clone(), 10);
+            threads.push(thread::spawn(move || {
+                let mut buf = Vec::new();
+                r.read_to_end(&mut buf).expect("read_to_end");
+            }))
+        }
+        for t in threads {
+            t.join().unwrap();
+        }
+        let snap = mp.metrics().snapshot();
+        assert_eq!(snap.bytes(), 200);
+        assert_eq!(snap.latency().count(), snap.reads());
+    }
+
+    #[test]
+    fn test_latency_buckets() {
+        assert_eq!(latency_bucket(Duration::from_nanos(0)), 0);
+        assert_eq!(latency_bucket(Duration::from_nanos(1)), 1);
+        assert_eq!(latency_bucket(Duration::from_nanos(1023)), 10);
+        assert_eq!(latency_bucket(Duration::from_nanos(1024)), 11);
+        assert_eq!(latency_bucket(Duration::from_secs(60)), 31);
+
+        let mut h = LatencyHistogram::default();
+        assert_eq!(h.quantile(0.5), None);
+        h.counts[3] = 9;
+        h.counts[10] = 1;
+        assert_eq!(h.quantile(0.5), Some(Duration::from_nanos(8)));
+        assert_eq!(h.quantile(0.9), Some(Duration::from_nanos(8)));
+        assert_eq!(h.quantile(1.0), Some(Duration::from_nanos(1024)));
+        assert_eq!(h.upper_bound(31), None);
+    }
+}
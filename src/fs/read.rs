@@ -6,7 +6,10 @@ use std::io::{Error, ErrorKind, Read, Seek, SeekFrom};
 
 use crate::fs::PosRead;
 
-#[cfg(feature = "mmap")] use memmap::{Mmap, MmapOptions};
+#[cfg(feature = "mmap")]
+use crate::mem::{
+    MapOptions, MappedSlice, MappedSliceMut, MemHandle, MemHandleMut
+};
 
 /// Re-implements `Read` and `Seek` over `PosRead` using _only_ positioned
 /// reads, and by maintaining an instance independent position.
@@ -90,26 +93,8 @@ impl<P> ReadPos<P>
     /// Seek by signed offset from an origin, checking for underflow and
     /// overflow.
     fn seek_from(&mut self, origin: u64, offset: i64) -> io::Result<u64> {
-        let checked_pos = if offset < 0 {
-            origin.checked_sub((-offset) as u64)
-        } else {
-            origin.checked_add(offset as u64)
-        };
-
-        if let Some(p) = checked_pos {
-            self.pos = p;
-            Ok(p)
-        } else if offset < 0 {
-            Err(Error::new(
-                ErrorKind::InvalidInput,
-                "Attempted seek to a negative absolute position"
-            ))
-        } else {
-            Err(Error::new(
-                ErrorKind::Other,
-                "Attempted seek would overflow u64 position"
-            ))
-        }
+        self.pos = seek_offset(origin, offset)?;
+        Ok(self.pos)
     }
 }
 
@@ -227,25 +212,8 @@ impl<P> ReadSlice<P>
     /// Seek by signed offset from an (absolute) origin, checking for
     /// underflow and overflow.
     fn seek_from(&mut self, origin: u64, offset: i64) -> io::Result<u64> {
-        let checked_pos = if offset < 0 {
-            origin.checked_sub((-offset) as u64)
-        } else {
-            origin.checked_add(offset as u64)
-        };
-
-        if let Some(p) = checked_pos {
-            self.seek_to(p)
-        } else if offset < 0 {
-            Err(Error::new(
-                ErrorKind::InvalidInput,
-                "Attempted seek to a negative position"
-            ))
-        } else {
-            Err(Error::new(
-                ErrorKind::Other,
-                "Attempted seek would overflow u64 position"
-            ))
-        }
+        let p = seek_offset(origin, offset)?;
+        self.seek_to(p)
     }
 
     /// Seek by absolute position, validated with the start index. Return the
@@ -358,22 +326,94 @@ impl<P> ReadSlice<P>
 impl<P> ReadSlice<P>
     where P: PosRead + Borrow<File>
 {
-    /// Return a new read-only memory map handle `Mmap` for the complete
-    /// region of the underlying `File`, from start to end.
+    /// Return a new read-only memory map for the complete region of the
+    /// underlying `File`, from start to end.
+    ///
+    /// The start offset need not be page aligned: the page-aligned superset
+    /// is mapped, and only the start..end range is exposed. A zero length
+    /// slice returns an empty `MappedSlice` without mapping. Returns an
+    /// error if the range can't be addressed on the current platform (e.g.
+    /// larger than 4 GiB on 32-bit).
     ///
     /// This requires the _mmap_ feature.
-    pub fn mem_map(&self) -> Result<Mmap, io::Error> {
-        let offset = self.start;
-        let len = self.len();
-        // See: https://github.com/danburkert/memmap-rs/pull/65
-        assert!(len  > 0);
-        assert!(len <= usize::max_value() as u64);
-        unsafe {
-            MmapOptions::new()
-                .offset(offset)
-                .len(len as usize)
-                .map(self.pos_read.borrow())
-        }
+    pub fn mem_map(&self) -> Result<MappedSlice, io::Error> {
+        MappedSlice::map(self.pos_read.borrow(), self.start, self.len())
+    }
+
+    /// Return a new [`MemHandle`] over a read-only memory map of the
+    /// complete region of the underlying `File`, from start to end, as per
+    /// [`ReadSlice::mem_map`]. The handle is ready for use with
+    /// `MemHandle::advise`.
+    ///
+    /// This requires the _mmap_ feature.
+    pub fn mem_map_handle(&self) -> Result<MemHandle<MappedSlice>, io::Error> {
+        self.mem_map_with(&MapOptions::new())
+    }
+
+    /// Return a new [`MemHandle`] over a read-only memory map of the
+    /// complete region of the underlying `File`, from start to end, as per
+    /// [`ReadSlice::mem_map`], with the given options applied.
+    ///
+    /// This requires the _mmap_ feature.
+    pub fn mem_map_with(&self, options: &MapOptions)
+        -> Result<MemHandle<MappedSlice>, io::Error>
+    {
+        options.handle(self.mem_map()?)
+    }
+
+    /// Return a new [`MemHandle`] over a private, copy-on-write memory map
+    /// (`MAP_PRIVATE`) of the complete region of the underlying `File`,
+    /// from start to end, with the given options applied. Writes, for
+    /// example via `MemHandle::get_mut`, are not carried through to the
+    /// file.
+    ///
+    /// This requires the _mmap_ feature.
+    pub fn mem_map_copy(&self, options: &MapOptions)
+        -> Result<MemHandle<MappedSliceMut>, io::Error>
+    {
+        let map = MappedSliceMut::map_copy(
+            self.pos_read.borrow(), self.start, self.len())?;
+        options.handle(map)
+    }
+
+    /// Return a new [`MemHandleMut`] over a shared, read-write memory map
+    /// of the complete region of the underlying `File`, from start to end,
+    /// with the given options applied. The `File` must be open for both
+    /// reading and writing. Writes are carried through to the file, and may
+    /// be flushed via `MemHandleMut::flush`.
+    ///
+    /// This requires the _mmap_ feature.
+    pub fn mem_map_mut(&self, options: &MapOptions)
+        -> Result<MemHandleMut<MappedSliceMut>, io::Error>
+    {
+        let map = MappedSliceMut::map_mut(
+            self.pos_read.borrow(), self.start, self.len())?;
+        options.handle_mut(map)
+    }
+}
+
+/// Return the position at signed offset from an absolute origin, or an
+/// error on underflow (before 0) or overflow of `u64`. Shared by the
+/// `Seek` implementations of this crate.
+pub(crate) fn seek_offset(origin: u64, offset: i64) -> io::Result<u64> {
+    let checked_pos = if offset < 0 {
+        origin.checked_sub((-offset) as u64)
+    } else {
+        origin.checked_add(offset as u64)
+    };
+
+    if let Some(p) = checked_pos {
+        Ok(p)
+    } else if offset < 0 {
+        Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Attempted seek to a negative position"
+        ))
+    } else {
+        Err(Error::new(
+            ErrorKind::Other,
+            "Attempted seek would overflow u64 position"
+        ))
     }
 }
 
@@ -386,6 +426,7 @@ mod tests {
 
     use tempfile::tempfile;
     use super::*;
+    use crate::fs::{MeteredPosRead, RateLimiter, ThrottledPosRead};
 
     #[test]
     fn test_seek() {
@@ -550,17 +591,103 @@ mod tests {
         assert_eq!(0, l);
     }
 
-    fn is_send<T: Send>() -> bool { true }
-    fn is_sync<T: Sync>() -> bool { true }
+    #[test]
+    #[cfg(feature = "mmap")]
+    fn test_mem_map() {
+        let mut f = tempfile().unwrap();
+        f.write_all(b"01234567890").unwrap();
+
+        let r1 = ReadSlice::new(&f, 1, 11);
+        let m = r1.mem_map().unwrap();
+        assert_eq!(&m[..], b"1234567890");
+        let m = r1.subslice(3, 7).mem_map().unwrap();
+        assert_eq!(&m[..], b"4567");
+        let m = r1.subslice(3, 3).mem_map().unwrap();
+        assert!(m.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "mmap")]
+    fn test_mem_map_handle() {
+        use crate::mem::MemAdvice;
+
+        let mut f = tempfile().unwrap();
+        f.write_all(&vec![1u8; 64 * 1024]).unwrap();
+
+        let r1 = ReadSlice::new(&f, 1, 64 * 1024);
+        let h = r1.subslice(4095, 8199).mem_map_handle().unwrap();
+        assert_eq!(h.len(), 4104);
+        assert_eq!(h.advise(MemAdvice::Random).unwrap(), MemAdvice::Random);
+        assert_eq!(h[0], 1u8);
+
+        let h = r1.subslice(7, 7).mem_map_handle().unwrap();
+        assert_eq!(h.advise(MemAdvice::Sequential).unwrap(),
+                   MemAdvice::Sequential);
+    }
+
+    #[test]
+    #[cfg(feature = "mmap")]
+    fn test_mem_map_copy() {
+        let mut f = tempfile().unwrap();
+        f.write_all(b"01234567890").unwrap();
+
+        let r1 = ReadSlice::new(&f, 1, 11);
+        let opts = MapOptions::new().populate();
+        let mut h = r1.mem_map_copy(&opts).unwrap();
+        h.get_mut().unwrap()[..2].copy_from_slice(b"xy");
+        assert_eq!(&h[..], b"xy34567890");
+
+        let h2 = h.clone();
+        assert!(h.get_mut().is_none());
+        drop(h2);
+        assert!(h.get_mut().is_some());
+
+        let m = r1.mem_map_with(&opts).unwrap();
+        assert_eq!(&m[..], b"1234567890");
+    }
+
+    #[test]
+    #[cfg(feature = "mmap")]
+    fn test_mem_map_mut() {
+        use crate::mem::MemAdvice;
+
+        let mut f = tempfile().unwrap();
+        f.write_all(b"01234567890").unwrap();
+
+        let r1 = ReadSlice::new(&f, 1, 11);
+        let mut h = r1.subslice(2, 6).mem_map_mut(&MapOptions::new()).unwrap();
+        assert_eq!(h.advise(MemAdvice::Random).unwrap(), MemAdvice::Random);
+        h.copy_from_slice(b"abcd");
+        h.flush().unwrap();
+
+        let mut buf = Vec::new();
+        r1.clone().read_to_end(&mut buf).unwrap();
+        assert_eq!(&buf, b"12abcd7890");
+
+        let h = h.into_handle();
+        assert_eq!(&h[..], b"abcd");
+    }
+
+    // Compile time assertions, shared by the other fs types
+    fn assert_send_sync<T: Send + Sync>() {}
 
     #[test]
     fn test_send_sync() {
-        assert!(is_send::<ReadPos<File>>());
-        assert!(is_sync::<ReadPos<File>>());
-        assert!(is_send::<ReadPos<Arc<File>>>());
-        assert!(is_sync::<ReadPos<Arc<File>>>());
-        assert!(is_send::<ReadSlice<Arc<File>>>());
-        assert!(is_sync::<ReadSlice<Arc<File>>>());
+        assert_send_sync::<ReadPos<File>>();
+        assert_send_sync::<ReadPos<Arc<File>>>();
+        assert_send_sync::<ReadSlice<Arc<File>>>();
+        assert_send_sync::<MeteredPosRead<Arc<File>>>();
+        assert_send_sync::<ReadSlice<MeteredPosRead<Arc<File>>>>();
+        assert_send_sync::<ThrottledPosRead<Arc<File>>>();
+        assert_send_sync::<RateLimiter>();
+    }
+
+    #[test]
+    #[cfg(feature = "crypto")]
+    fn test_send_sync_crypto() {
+        use crate::crypto::{DecryptingPosRead, EncryptingWriter};
+        assert_send_sync::<DecryptingPosRead<Arc<File>>>();
+        assert_send_sync::<EncryptingWriter<File>>();
     }
 
     fn is_pos_read<T: PosRead>() -> bool { true }
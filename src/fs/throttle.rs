@@ -0,0 +1,438 @@
+use std::io;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::fs::PosRead;
+
+/// Wraps any `PosRead`, blocking `pread` callers as needed to conform to the
+/// bandwidth and operation rate limits of a shared [`RateLimiter`].
+///
+/// The limiter is held via `Arc`, so all clones of this wrapper, and thus all
+/// `ReadPos` or `ReadSlice` instances derived from the same source, share the
+/// same budget. Any number of independent sources may also be wrapped with
+/// the same limiter, for example to limit all background reads from a disk.
+///
+/// ## Example
+///
+/// ``` rust
+/// # use std::io;
+/// use std::io::{Read, Write};
+/// use std::sync::Arc;
+/// use olio::fs::{RateLimiter, RateLimits, ReadSlice, ThrottledPosRead};
+/// use tempfile::tempfile;
+///
+/// # fn run() -> Result<(), io::Error> {
+/// let mut file = tempfile()?;
+/// file.write_all(b"0123456789")?;
+///
+/// let limiter = Arc::new(RateLimiter::new(
+///     RateLimits::unlimited()
+///         .bytes_per_sec(64 * 1024 * 1024, 1024 * 1024)
+///         .ops_per_sec(10_000, 100)
+/// ));
+/// let mut rslice = ReadSlice::new(
+///     ThrottledPosRead::new(&file, limiter.clone()), 0, 10);
+///
+/// let mut buf = Vec::new();
+/// rslice.read_to_end(&mut buf)?;
+/// assert_eq!(&buf, b"0123456789");
+///
+/// // Adjust at runtime, for all wrapped sources
+/// limiter.set_limits(RateLimits::unlimited());
+/// # Ok(())
+/// # }
+/// # run().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct ThrottledPosRead<P>
+    where P: PosRead
+{
+    pos_read: P,
+    limiter: Arc<RateLimiter>,
+}
+
+impl<P> ThrottledPosRead<P>
+    where P: PosRead
+{
+    /// New instance wrapping a `PosRead` and limited by the given (possibly
+    /// shared) limiter.
+    pub fn new(pos_read: P, limiter: Arc<RateLimiter>) -> Self {
+        ThrottledPosRead { pos_read, limiter }
+    }
+
+    /// Return a reference to the shared limiter.
+    pub fn limiter(&self) -> &Arc<RateLimiter> {
+        &self.limiter
+    }
+
+    /// Return a reference to the inner `PosRead`.
+    pub fn get_ref(&self) -> &P {
+        &self.pos_read
+    }
+
+    /// Unwrap and return the inner `PosRead`.
+    pub fn into_inner(self) -> P {
+        self.pos_read
+    }
+}
+
+impl<P> Clone for ThrottledPosRead<P>
+    where P: PosRead + Clone
+{
+    /// Return a new instance by clone of the inner `PosRead`, limited by the
+    /// same shared limiter as self.
+    fn clone(&self) -> Self {
+        ThrottledPosRead { pos_read: self.pos_read.clone(),
+                           limiter: self.limiter.clone() }
+    }
+}
+
+impl<P> PosRead for ThrottledPosRead<P>
+    where P: PosRead
+{
+    /// Block until the limiter budget allows a read of `buf.len()` bytes,
+    /// then read. Any unused portion of the byte budget (for short reads or
+    /// errors) is returned to the limiter.
+    fn pread(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        let requested = buf.len() as u64;
+        self.limiter.acquire(requested);
+        let res = self.pos_read.pread(buf, offset);
+        let used = match res {
+            Ok(len) => len as u64,
+            Err(_) => 0,
+        };
+        if used < requested {
+            self.limiter.refund(requested - used);
+        }
+        res
+    }
+}
+
+/// Bandwidth (bytes/second) and operation (IOPS) limits for a
+/// [`RateLimiter`].
+///
+/// Each limit is optional, and configured with a sustained rate per second
+/// and a burst size, which is the maximum budget that can accumulate while
+/// idle.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RateLimits {
+    bytes: Option<Rate>,
+    ops: Option<Rate>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Rate {
+    per_sec: u64,
+    burst: u64,
+}
+
+impl RateLimits {
+    /// New instance with no limits.
+    pub fn unlimited() -> RateLimits {
+        RateLimits::default()
+    }
+
+    /// Set the bandwidth limit in bytes per second, with the given burst
+    /// size in bytes. A single read larger than the burst size is allowed
+    /// once the full burst is available, with the excess borrowed from
+    /// subsequent budget. This _panics_ if rate or burst is 0.
+    pub fn bytes_per_sec(mut self, rate: u64, burst: u64) -> RateLimits {
+        assert!(rate > 0 && burst > 0);
+        self.bytes = Some(Rate { per_sec: rate, burst });
+        self
+    }
+
+    /// Set the operation limit in reads per second, with the given burst size
+    /// in reads. This _panics_ if rate or burst is 0.
+    pub fn ops_per_sec(mut self, rate: u64, burst: u64) -> RateLimits {
+        assert!(rate > 0 && burst > 0);
+        self.ops = Some(Rate { per_sec: rate, burst });
+        self
+    }
+}
+
+/// A token bucket rate limiter for bytes and operations, which may be shared
+/// by any number of [`ThrottledPosRead`] instances.
+///
+/// Callers block (via `Condvar`) until budget is available. Limits may be
+/// adjusted at runtime via [`RateLimiter::set_limits`], which also wakes any
+/// blocked callers to re-evaluate against the new limits.
+#[derive(Debug)]
+pub struct RateLimiter {
+    state: Mutex<Buckets>,
+    cond: Condvar,
+}
+
+#[derive(Debug)]
+struct Buckets {
+    limits: RateLimits,
+    bytes: f64,
+    ops: f64,
+    last: Instant,
+}
+
+impl RateLimiter {
+    /// New instance with the given limits, starting with a full burst
+    /// budget.
+    pub fn new(limits: RateLimits) -> RateLimiter {
+        let mut b = Buckets {
+            limits,
+            bytes: 0.0,
+            ops: 0.0,
+            last: Instant::now(),
+        };
+        b.fill();
+        RateLimiter { state: Mutex::new(b), cond: Condvar::new() }
+    }
+
+    /// Return the current limits.
+    pub fn limits(&self) -> RateLimits {
+        self.lock().limits
+    }
+
+    /// Replace the current limits. Available budget is capped at any new
+    /// burst size, and any blocked callers are woken to re-evaluate.
+    pub fn set_limits(&self, limits: RateLimits) {
+        let mut b = self.lock();
+        b.refill(Instant::now());
+        // Previously unlimited budgets start full
+        if b.limits.bytes.is_none() { b.bytes = std::f64::MAX; }
+        if b.limits.ops.is_none()   { b.ops = std::f64::MAX; }
+        b.limits = limits;
+        b.cap();
+        drop(b);
+        self.cond.notify_all();
+    }
+
+    /// Block until budget for one read operation of the given byte length
+    /// is available, and consume it.
+    pub fn acquire(&self, bytes: u64) {
+        let mut b = self.lock();
+        loop {
+            b.refill(Instant::now());
+            match b.wait_time(bytes) {
+                None => {
+                    b.consume(bytes);
+                    return;
+                }
+                Some(wait) => {
+                    b = match self.cond.wait_timeout(b, wait) {
+                        Ok((g, _)) => g,
+                        Err(e) => e.into_inner().0,
+                    };
+                }
+            }
+        }
+    }
+
+    /// Return unused byte budget, for example after a short read.
+    pub fn refund(&self, bytes: u64) {
+        let mut b = self.lock();
+        b.bytes += bytes as f64;
+        b.cap();
+        drop(b);
+        self.cond.notify_all();
+    }
+
+    // The state remains consistent even if a panic occurred while held, so
+    // recover from poisoning.
+    fn lock(&self) -> MutexGuard<'_, Buckets> {
+        match self.state.lock() {
+            Ok(g) => g,
+            Err(e) => e.into_inner(),
+        }
+    }
+}
+
+impl Buckets {
+    // Set all budget to full burst.
+    fn fill(&mut self) {
+        self.bytes = self.limits.bytes.map_or(0.0, |r| r.burst as f64);
+        self.ops = self.limits.ops.map_or(0.0, |r| r.burst as f64);
+    }
+
+    // Cap budget at burst size.
+    fn cap(&mut self) {
+        if let Some(r) = self.limits.bytes {
+            self.bytes = self.bytes.min(r.burst as f64);
+        }
+        if let Some(r) = self.limits.ops {
+            self.ops = self.ops.min(r.burst as f64);
+        }
+    }
+
+    // Add budget accumulated since last refill.
+    fn refill(&mut self, now: Instant) {
+        if now > self.last {
+            let secs = (now - self.last).as_secs_f64();
+            if let Some(r) = self.limits.bytes {
+                self.bytes += secs * r.per_sec as f64;
+            }
+            if let Some(r) = self.limits.ops {
+                self.ops += secs * r.per_sec as f64;
+            }
+            self.last = now;
+            self.cap();
+        }
+    }
+
+    // Return time to wait for sufficient budget, or None if available now.
+    fn wait_time(&self, bytes: u64) -> Option<Duration> {
+        let mut wait = 0.0f64;
+        if let Some(r) = self.limits.bytes {
+            let need = bytes.min(r.burst) as f64;
+            if self.bytes < need {
+                wait = wait.max((need - self.bytes) / r.per_sec as f64);
+            }
+        }
+        if let Some(r) = self.limits.ops {
+            if self.ops < 1.0 {
+                wait = wait.max((1.0 - self.ops) / r.per_sec as f64);
+            }
+        }
+        if wait > 0.0 {
+            // Round up to at least a microsecond, to avoid spinning
+            Some(Duration::from_secs_f64(wait.max(1e-6)))
+        } else {
+            None
+        }
+    }
+
+    fn consume(&mut self, bytes: u64) {
+        if self.limits.bytes.is_some() {
+            self.bytes -= bytes as f64;
+        }
+        if self.limits.ops.is_some() {
+            self.ops -= 1.0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::{Read, Write};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use tempfile::tempfile;
+
+    use super::*;
+    use crate::fs::{ReadPos, ReadSlice};
+
+    fn create_file(len: usize) -> File {
+        let mut f = tempfile().unwrap();
+        f.write_all(&vec![1u8; len]).unwrap();
+        f
+    }
+
+    // Refill the limiter's buckets as of the given time after the last
+    // refill, and return the time to wait for a read of the given length.
+    // This avoids any dependence on elapsed wall-clock time.
+    fn wait_after(limiter: &RateLimiter, after: Duration, bytes: u64)
+        -> Option<Duration>
+    {
+        let mut b = limiter.lock();
+        let now = b.last + after;
+        b.refill(now);
+        b.wait_time(bytes)
+    }
+
+    #[test]
+    fn test_unlimited() {
+        let f = create_file(1024);
+        let limiter = Arc::new(RateLimiter::new(RateLimits::unlimited()));
+        let mut r = ReadPos::new(ThrottledPosRead::new(&f, limiter), 1024);
+        let mut buf = Vec::new();
+        r.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf.len(), 1024);
+    }
+
+    #[test]
+    fn test_bytes_limited() {
+        let f = create_file(3000);
+        let limiter = Arc::new(RateLimiter::new(
+            RateLimits::unlimited().bytes_per_sec(10_000, 1_000)
+        ));
+        let r = ReadSlice::new(
+            ThrottledPosRead::new(&f, limiter.clone()), 0, 3000);
+        let mut buf = [0u8; 1000];
+        r.subslice(0, 1000).read_exact(&mut buf).unwrap();
+
+        // Initial burst is consumed, so the next read waits 100ms
+        let ms = |m| Duration::from_millis(m);
+        let wait = wait_after(&limiter, ms(0), 1000).unwrap();
+        assert!(wait > ms(99) && wait <= ms(100), "{:?}", wait);
+        let wait = wait_after(&limiter, ms(50), 1000).unwrap();
+        assert!(wait > ms(49) && wait <= ms(50), "{:?}", wait);
+        assert_eq!(wait_after(&limiter, ms(50), 1000), None);
+
+        // Budget is capped at the burst size
+        assert_eq!(wait_after(&limiter, ms(500), 1000), None);
+        assert_eq!(limiter.lock().bytes, 1000.0);
+        // A read larger than the burst only waits for the full burst
+        assert_eq!(wait_after(&limiter, ms(0), 5000), None);
+    }
+
+    #[test]
+    fn test_refund_short() {
+        let f = create_file(100);
+        let limiter = Arc::new(RateLimiter::new(
+            RateLimits::unlimited().bytes_per_sec(1_000, 1_000)
+        ));
+        let r = ThrottledPosRead::new(&f, limiter.clone());
+        let mut buf = [0u8; 1000];
+        assert_eq!(r.pread(&mut buf, 0).unwrap(), 100);
+
+        // Budget for the 900 unread bytes was refunded
+        assert!(limiter.lock().bytes >= 900.0);
+        assert_eq!(limiter.lock().wait_time(800), None);
+        assert_eq!(r.pread(&mut buf[..800], 0).unwrap(), 100);
+    }
+
+    #[test]
+    fn test_ops_limited() {
+        let f = create_file(10);
+        let limiter = Arc::new(RateLimiter::new(
+            RateLimits::unlimited().ops_per_sec(100, 1)
+        ));
+        let r = ThrottledPosRead::new(&f, limiter.clone());
+        let mut buf = [0u8; 1];
+        r.pread(&mut buf, 0).unwrap();
+
+        // Burst of 1 is consumed, with the next op available after 10ms,
+        // independent of read length.
+        let ms = |m| Duration::from_millis(m);
+        let wait = wait_after(&limiter, ms(0), 1).unwrap();
+        assert!(wait > ms(9) && wait <= ms(10), "{:?}", wait);
+        assert_eq!(wait_after(&limiter, ms(0), 1_000_000), Some(wait));
+        assert_eq!(wait_after(&limiter, ms(10), 1), None);
+
+        for _ in 0..4 {
+            r.pread(&mut buf, 0).unwrap();
+        }
+        assert!(limiter.lock().ops < 1.0);
+    }
+
+    #[test]
+    fn test_set_limits_wakes() {
+        let f = Arc::new(create_file(1000));
+        // After the burst, the next full read would wait 1000 seconds
+        let limiter = Arc::new(RateLimiter::new(
+            RateLimits::unlimited().bytes_per_sec(1, 1000)
+        ));
+        let r = ThrottledPosRead::new(f, limiter.clone());
+        let mut buf = [0u8; 1000];
+        assert_eq!(r.pread(&mut buf, 0).unwrap(), 1000); // consume burst
+
+        let t = thread::spawn(move || {
+            let mut buf = [0u8; 1000];
+            r.pread(&mut buf, 0).unwrap()
+        });
+        thread::sleep(Duration::from_millis(20));
+        limiter.set_limits(RateLimits::unlimited());
+        assert_eq!(t.join().unwrap(), 1000);
+        assert_eq!(limiter.limits(), RateLimits::unlimited());
+    }
+}
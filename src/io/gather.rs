@@ -1,5 +1,5 @@
 use std::io;
-use std::io::{Cursor, Read};
+use std::io::{BufRead, Cursor, Read};
 
 /// A specialized reader presenting a continuous (gathered) `Read` interface
 /// over N non-contiguous byte buffers.
@@ -7,6 +7,9 @@ use std::io::{Cursor, Read};
 /// This is more efficient than the current implementation of
 /// `std::io::Cursor::chain` for many reads over many buffers. See the
 /// associated benchmark comparison.
+///
+/// `BufRead` is also implemented without copying, with `fill_buf` returning
+/// the remainder of the current buffer.
 pub struct GatheringReader<'a, T: AsRef<[u8]>> {
     current: Cursor<&'a [u8]>,
     remainder: &'a [T]
@@ -34,6 +37,53 @@ impl<'a, T: AsRef<[u8]> + 'a> GatheringReader<'a, T> {
             None => false
         }
     }
+
+    // Skip cnt bytes, across buffers as needed. Returns false, positioned at
+    // the end, if fewer than cnt bytes remain.
+    fn skip(&mut self, mut cnt: usize) -> bool {
+        loop {
+            let pos = self.current.position() as usize;
+            let len = self.current.get_ref().len();
+            if cnt <= len - pos {
+                self.current.set_position((pos + cnt) as u64);
+                return true;
+            }
+            cnt -= len - pos;
+            if !self.pop() {
+                self.current.set_position(len as u64);
+                return false;
+            }
+        }
+    }
+}
+
+/// Zero-copy access to the remaining bytes, via `bytes::Buf`. Note that
+/// `remaining` sums the lengths of all remaining buffers on each call.
+#[cfg(feature = "bytes")]
+impl<'a, T: AsRef<[u8]> + 'a> bytes::Buf for GatheringReader<'a, T> {
+    fn remaining(&self) -> usize {
+        let pos = self.current.position() as usize;
+        let cur = self.current.get_ref().len() - pos;
+        self.remainder.iter().fold(cur, |n, b| n + b.as_ref().len())
+    }
+
+    fn chunk(&self) -> &[u8] {
+        let cur = &self.current.get_ref()[self.current.position() as usize..];
+        if !cur.is_empty() {
+            return cur;
+        }
+        for b in self.remainder {
+            if !b.as_ref().is_empty() {
+                return b.as_ref();
+            }
+        }
+        &[]
+    }
+
+    fn advance(&mut self, cnt: usize) {
+        let skipped = self.skip(cnt);
+        assert!(skipped, "advance past end of GatheringReader");
+    }
 }
 
 impl<'a, T: AsRef<[u8]> + 'a> Read for GatheringReader<'a, T> {
@@ -47,6 +97,21 @@ impl<'a, T: AsRef<[u8]> + 'a> Read for GatheringReader<'a, T> {
     }
 }
 
+impl<'a, T: AsRef<[u8]> + 'a> BufRead for GatheringReader<'a, T> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        // Skip any exhausted or empty buffers
+        let mut pos = self.current.position() as usize;
+        while pos >= self.current.get_ref().len() && self.pop() {
+            pos = 0;
+        }
+        Ok(&self.current.get_ref()[pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.skip(amt);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -71,6 +136,63 @@ mod tests {
         assert_eq!("hello world", &obuf[..]);
     }
 
+    #[test]
+    #[cfg(feature = "bytes")]
+    fn test_buf() {
+        use bytes::Buf;
+
+        let bufs: Vec<&[u8]> = vec![b"hello ", b"", b"wor", b"", b"ld"];
+        let mut rdr = GatheringReader::new(&bufs);
+        assert_eq!(rdr.remaining(), 11);
+        assert_eq!(rdr.chunk(), b"hello ");
+        rdr.advance(6);
+        assert_eq!(rdr.chunk(), b"wor");
+        assert_eq!(rdr.get_u16(), u16::from_be_bytes(*b"wo"));
+        let mut obuf = [0u8; 3];
+        rdr.copy_to_slice(&mut obuf);
+        assert_eq!(&obuf, b"rld");
+        assert!(!rdr.has_remaining());
+        assert!(rdr.chunk().is_empty());
+    }
+
+    #[test]
+    fn test_buf_read() {
+        let bufs: Vec<&[u8]> = vec![b"", b"hel", b"", b"lo", b" wo", b"rld"];
+        let mut rdr = GatheringReader::new(&bufs);
+        assert_eq!(rdr.fill_buf().unwrap(), b"hel");
+        rdr.consume(2);
+        assert_eq!(rdr.fill_buf().unwrap(), b"l");
+        rdr.consume(3); // across buffers
+        assert_eq!(rdr.fill_buf().unwrap(), b" wo");
+        rdr.consume(100); // clamped at end
+        assert!(rdr.fill_buf().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_read_line() {
+        let bufs: Vec<&[u8]> = vec![
+            b"first li", b"ne\r", b"\nsecond", b"", b"\n", b"\nlast"
+        ];
+        let mut rdr = GatheringReader::new(&bufs);
+        let mut line = String::new();
+        assert_eq!(rdr.read_line(&mut line).unwrap(), 12);
+        assert_eq!(line, "first line\r\n");
+        let lines: Vec<String> = rdr.lines().map(|l| l.unwrap()).collect();
+        assert_eq!(lines, vec!["second", "", "last"]);
+
+        let bufs: Vec<&[u8]> = vec![b"a,b", b"b,", b",c"];
+        let mut rdr = GatheringReader::new(&bufs);
+        let mut field = Vec::new();
+        rdr.read_until(b',', &mut field).unwrap();
+        assert_eq!(&field[..], b"a,");
+        field.clear();
+        rdr.read_until(b',', &mut field).unwrap();
+        assert_eq!(&field[..], b"bb,");
+        let fields: Vec<Vec<u8>> = rdr.split(b',').map(|f| f.unwrap())
+            .collect();
+        assert_eq!(fields, vec![b"".to_vec(), b"c".to_vec()]);
+    }
+
     #[test]
     fn test_empty() {
         let bufs: Vec<&[u8]> = vec![];
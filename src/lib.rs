@@ -6,6 +6,8 @@
 //! The [`fs`] module includes a [`fs::PosRead`] trait, offering a uniform
 //! `pread` for positioned file reads, and a [`fs::ReadSlice`] supporting
 //! multiple independent reader instances limited to a fixed start..end range.
+//! The [`fs::MeteredPosRead`] wrapper records read metrics for any `PosRead`,
+//! and [`fs::ThrottledPosRead`] limits read bandwidth and operation rates.
 //!
 //! The [`io`] module includes a [`io::GatheringReader`], which presents a
 //! continuous `Read` interface over N non-contiguous byte buffers.
@@ -15,8 +17,21 @@
 //!
 //! ## Optional Features
 //!
-//! _mmap (default):_ Adds [`fs::ReadSlice::mem_map`] support for memory
-//! mapping.
+//! _mmap (default):_ Adds [`fs::ReadSlice::mem_map`] and
+//! [`fs::ReadSlice::mem_map_handle`] support for memory mapping, via
+//! [`mem::MappedSlice`].
+//!
+//! _linux-advice:_ Adds `mem::MemHandle::advise_linux` for Linux-specific
+//! memory advice flags, such as transparent huge pages, via
+//! `mem::LinuxAdvice`. This has no effect on other platforms.
+//!
+//! _bytes:_ Adds zero-copy conversion of [`mem::MemHandle`] to
+//! `bytes::Bytes`, and `bytes::Buf` access for [`io::GatheringReader`].
+//! This feature requires a newer rust (1.57+) than the MSRV of this crate.
+//!
+//! _crypto:_ Adds the `crypto` module, with random access decryption of
+//! stream cipher encrypted content via a `PosRead` wrapper. This feature
+//! requires a newer rust (1.56+) than the MSRV of this crate.
 #![warn(rust_2018_idioms)]
 
 /// The crate version string.
@@ -76,6 +91,15 @@ pub mod fs {
 
     mod read;
     pub use read::{ReadPos, ReadSlice};
+    pub(crate) use read::seek_offset;
+
+    mod metered;
+    pub use metered::{
+        LatencyHistogram, MeteredPosRead, ReadMetrics, ReadSnapshot
+    };
+
+    mod throttle;
+    pub use throttle::{RateLimiter, RateLimits, ThrottledPosRead};
 
     /// Compatibility type aliases.
     pub mod rc {
@@ -93,8 +117,71 @@ pub mod io {
     pub use gather::GatheringReader;
 }
 
+/// Random access encryption and decryption via seekable stream ciphers
+///
+/// A [`crypto::SeekableCipher`] (AES-CTR or ChaCha20) applies its keystream
+/// at any byte offset. The [`crypto::DecryptingPosRead`] wrapper uses this to
+/// offer a decrypting `PosRead`, which may be used with `ReadPos` and
+/// `ReadSlice`, and the [`crypto::EncryptingWriter`] produces matching
+/// encrypted content.
+///
+/// This requires the _crypto_ feature.
+#[cfg(feature = "crypto")]
+pub mod crypto {
+    mod stream;
+    pub use stream::{DecryptingPosRead, EncryptingWriter, SeekableCipher};
+}
+
 /// Random access memory utilities
 pub mod mem {
+    mod adaptive;
+    pub use adaptive::AdaptiveReader;
+
+    mod aligned;
+    pub use aligned::AlignedBuf;
+
     mod handle;
-    pub use handle::{MemAdviseError, MemHandle, MemAdvice};
+    pub use handle::{
+        AdviceGuard, AdvicePolicy, AdviceSnapshot, LockGuard, MemAdviseError,
+        MemHandle, MemHandleMut, MemAdvice, WeakMemHandle
+    };
+
+    mod lock;
+    pub use lock::MemLockError;
+
+    mod page;
+    pub use page::page_size;
+
+    mod prefetch;
+    pub use prefetch::Prefetch;
+
+    mod ranges;
+
+    mod reader;
+    pub use reader::MemReader;
+
+    mod view;
+    pub use view::{
+        I16Be, I16Le, I32Be, I32Le, I64Be, I64Le, Pod, U16Be, U16Le, U32Be,
+        U32Le, U64Be, U64Le, ViewError
+    };
+
+    #[cfg(all(feature = "linux-advice",
+              any(target_os = "linux", target_os = "android")))]
+    mod linux;
+    #[cfg(all(feature = "linux-advice",
+              any(target_os = "linux", target_os = "android")))]
+    pub use linux::LinuxAdvice;
+
+    #[cfg(feature = "mmap")] mod map;
+    #[cfg(feature = "mmap")]
+    pub use map::{MapOptions, MappedSlice, MappedSliceMut};
+
+    #[cfg(feature = "mmap")] mod grow;
+    #[cfg(feature = "mmap")]
+    pub use grow::GrowableMap;
+
+    #[cfg(all(feature = "mmap", target_os = "linux"))] mod memfd;
+    #[cfg(all(feature = "mmap", target_os = "linux"))]
+    pub use memfd::MemFdWriter;
 }
@@ -0,0 +1,209 @@
+use std::cmp;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::Deref;
+
+use crate::fs::seek_offset;
+use crate::mem::{page_size, MemAdvice, MemHandle};
+
+// Score at or beyond which the pattern is switched, and the bound of the
+// score. The gap between the two gives hysteresis, such that an occasional
+// jump doesn't interrupt a sequential scan, or vice-versa.
+const THRESHOLD: i32 = 4;
+const SCORE_MAX: i32 = 8;
+
+/// A `Read + Seek` cursor over a [`MemHandle`], which observes the access
+/// pattern and advises `MemAdvice::Sequential` or `Random` accordingly.
+///
+/// Each read is classified as contiguous, if it starts at or shortly (less
+/// than a page) after the end of the prior read, or otherwise as a jump.
+/// Contiguous reads raise a score, and jumps lower it. Advice is only changed
+/// once the score crosses a threshold in either direction, so the pattern is
+/// stable for mostly-sequential or mostly-random access. Before then, the
+/// handle's advice is left at `MemAdvice::Normal`.
+///
+/// Advice is given via the reader's own handle, so is prioritized with that
+/// of other handles to the same memory as usual. Any error relaying advice
+/// is ignored, subject to the [`AdvicePolicy`](crate::mem::AdvicePolicy).
+#[derive(Debug)]
+pub struct AdaptiveReader<T>
+    where T: Deref<Target=[u8]>
+{
+    handle: MemHandle<T>,
+    pos: u64,
+    last_end: Option<u64>,
+    score: i32,
+    pattern: MemAdvice,
+}
+
+impl<T> AdaptiveReader<T>
+    where T: Deref<Target=[u8]>
+{
+    /// New reader over the given handle, positioned at the start. The
+    /// handle's current advice is replaced once a pattern is detected.
+    pub fn new(handle: MemHandle<T>) -> AdaptiveReader<T> {
+        AdaptiveReader {
+            handle,
+            pos: 0,
+            last_end: None,
+            score: 0,
+            pattern: MemAdvice::Normal,
+        }
+    }
+
+    /// Return the access pattern currently advised: `Normal` until a
+    /// pattern is detected, then `Sequential` or `Random`.
+    pub fn pattern(&self) -> MemAdvice {
+        self.pattern
+    }
+
+    /// Return a reference to the inner handle.
+    pub fn get_ref(&self) -> &MemHandle<T> {
+        &self.handle
+    }
+
+    /// Unwrap the inner handle, retaining any advice given.
+    pub fn into_inner(self) -> MemHandle<T> {
+        self.handle
+    }
+
+    // Classify a read starting at pos, and adjust advice as needed.
+    fn observe(&mut self, pos: u64) {
+        if let Some(last_end) = self.last_end {
+            let contiguous = pos >= last_end &&
+                pos - last_end < page_size() as u64;
+            self.score = if contiguous {
+                cmp::min(self.score + 1, SCORE_MAX)
+            } else {
+                cmp::max(self.score - 1, -SCORE_MAX)
+            };
+        }
+        let pattern = if self.score >= THRESHOLD {
+            MemAdvice::Sequential
+        } else if self.score <= -THRESHOLD {
+            MemAdvice::Random
+        } else {
+            self.pattern
+        };
+        if pattern != self.pattern {
+            self.pattern = pattern;
+            self.handle.advise(pattern).ok();
+        }
+    }
+}
+
+impl<T> Read for AdaptiveReader<T>
+    where T: Deref<Target=[u8]>
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.handle.len() as u64;
+        if buf.is_empty() || self.pos >= len {
+            return Ok(0);
+        }
+        let pos = self.pos;
+        self.observe(pos);
+        let start = pos as usize;
+        let n = cmp::min(buf.len(), self.handle.len() - start);
+        buf[..n].copy_from_slice(&self.handle[start..(start + n)]);
+        self.pos += n as u64;
+        self.last_end = Some(self.pos);
+        Ok(n)
+    }
+}
+
+impl<T> Seek for AdaptiveReader<T>
+    where T: Deref<Target=[u8]>
+{
+    fn seek(&mut self, from: SeekFrom) -> io::Result<u64> {
+        match from {
+            SeekFrom::Start(p) => {
+                self.pos = p;
+                Ok(p)
+            }
+            SeekFrom::End(offset) => {
+                let origin = self.handle.len() as u64;
+                self.pos = seek_offset(origin, offset)?;
+                Ok(self.pos)
+            }
+            SeekFrom::Current(offset) => {
+                let origin = self.pos;
+                self.pos = seek_offset(origin, offset)?;
+                Ok(self.pos)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Seek, SeekFrom};
+
+    use super::AdaptiveReader;
+    use crate::mem::{page_size, MemHandle};
+    use crate::mem::MemAdvice::*;
+
+    #[test]
+    fn test_sequential() {
+        let data: Vec<u8> = (0..=255).cycle().take(64 * 1024).collect();
+        let h = MemHandle::new(data.clone());
+        let mut r = AdaptiveReader::new(h.clone());
+        let mut buf = [0u8; 1000];
+        for _ in 0..4 {
+            r.read_exact(&mut buf).unwrap();
+            assert_eq!(r.pattern(), Normal);
+        }
+        r.read_exact(&mut buf).unwrap();
+        assert_eq!(r.pattern(), Sequential);
+        assert_eq!(h.advice_snapshot().advisors(Sequential), 1);
+
+        // An occasional jump doesn't change the pattern
+        r.seek(SeekFrom::Start(20_000)).unwrap();
+        r.read_exact(&mut buf).unwrap();
+        assert_eq!(r.pattern(), Sequential);
+        assert_eq!(&buf[..], &data[20_000..21_000]);
+
+        let mut rest = Vec::new();
+        r.read_to_end(&mut rest).unwrap();
+        assert_eq!(&rest[..], &data[21_000..]);
+        assert_eq!(r.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_random() {
+        let ps = page_size() as u64;
+        let h = MemHandle::new(vec![1u8; 64 * page_size()]);
+        let mut r = AdaptiveReader::new(h.clone());
+        let mut buf = [0u8; 16];
+        for &p in &[40, 3, 17, 60, 9, 33, 2] {
+            r.seek(SeekFrom::Start(p * ps)).unwrap();
+            r.read_exact(&mut buf).unwrap();
+        }
+        assert_eq!(r.pattern(), Random);
+        assert_eq!(h.advice_snapshot().advisors(Random), 1);
+
+        // Switching back requires sustained contiguous reads
+        for i in 0..7 {
+            r.read_exact(&mut buf).unwrap();
+            assert_eq!(r.pattern(), Random, "read {}", i);
+        }
+        for _ in 0..6 {
+            r.read_exact(&mut buf).unwrap();
+        }
+        assert_eq!(r.pattern(), Sequential);
+
+        let snap = r.into_inner().advice_snapshot();
+        assert_eq!(snap.advisors(Sequential), 1);
+        assert_eq!(snap.advisors(Random), 0);
+    }
+
+    #[test]
+    fn test_seek() {
+        let mut r = AdaptiveReader::new(MemHandle::new(vec![7u8; 10]));
+        assert_eq!(r.seek(SeekFrom::End(-3)).unwrap(), 7);
+        assert_eq!(r.seek(SeekFrom::Current(-2)).unwrap(), 5);
+        assert!(r.seek(SeekFrom::Current(-6)).is_err());
+        assert_eq!(r.seek(SeekFrom::Start(20)).unwrap(), 20);
+        let mut buf = [0u8; 4];
+        assert_eq!(r.read(&mut buf).unwrap(), 0);
+    }
+}
@@ -0,0 +1,192 @@
+use std::alloc::{alloc_zeroed, dealloc, handle_alloc_error, Layout};
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::ptr::NonNull;
+use std::slice;
+
+use crate::mem::page_size;
+
+// Huge page size assumed for alignment, the most common (PMD) size with 4 KiB
+// base pages, e.g. on x86_64 and aarch64.
+const HUGE_PAGE_SIZE: usize = 2 * 1024 * 1024;
+
+/// A fixed length, zero initialized heap buffer, allocated in whole,
+/// aligned pages.
+///
+/// Memory advice for a `Vec<u8>` via [`MemHandle`](crate::mem::MemHandle)
+/// is of limited use, as it applies to the page-aligned superset of the
+/// buffer, which may include unrelated heap allocations. The allocation of
+/// this type is aligned to, and rounded up to a multiple of, the page size
+/// (or optionally the huge page size), so advice applies only to its own
+/// pages.
+pub struct AlignedBuf {
+    ptr: NonNull<u8>,
+    len: usize,
+    align: usize,
+    capacity: usize,
+}
+
+impl AlignedBuf {
+    /// Allocate a new, zeroed buffer of the given length, aligned to the
+    /// page size.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the rounded length overflows `isize`.
+    pub fn new(len: usize) -> AlignedBuf {
+        AlignedBuf::with_align(len, page_size())
+    }
+
+    /// Allocate a new, zeroed buffer of the given length, aligned to the
+    /// huge page size (2 MiB), for use with transparent huge pages, e.g. via
+    /// `LinuxAdvice::HugePage`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the rounded length overflows `isize`.
+    pub fn huge(len: usize) -> AlignedBuf {
+        AlignedBuf::with_align(len, HUGE_PAGE_SIZE.max(page_size()))
+    }
+
+    fn with_align(len: usize, align: usize) -> AlignedBuf {
+        let capacity = len.checked_add(align - 1)
+            .map(|c| c & !(align - 1))
+            .expect("AlignedBuf capacity overflow");
+        if capacity == 0 {
+            // No allocation, but a non-null, aligned pointer is required
+            let ptr = NonNull::new(align as *mut u8).unwrap();
+            return AlignedBuf { ptr, len, align, capacity };
+        }
+        let layout = Layout::from_size_align(capacity, align)
+            .expect("AlignedBuf capacity overflow");
+        let ptr = unsafe { alloc_zeroed(layout) };
+        match NonNull::new(ptr) {
+            Some(ptr) => AlignedBuf { ptr, len, align, capacity },
+            None => handle_alloc_error(layout),
+        }
+    }
+
+    /// Return the alignment of the allocation in bytes.
+    pub fn align(&self) -> usize {
+        self.align
+    }
+
+    /// Return the size of the allocation in bytes, as rounded up to a
+    /// multiple of the alignment.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+impl<'a> From<&'a [u8]> for AlignedBuf {
+    /// Allocate a new, page aligned buffer with a copy of the given bytes.
+    fn from(bytes: &'a [u8]) -> AlignedBuf {
+        let mut buf = AlignedBuf::new(bytes.len());
+        buf.copy_from_slice(bytes);
+        buf
+    }
+}
+
+impl Clone for AlignedBuf {
+    fn clone(&self) -> AlignedBuf {
+        let mut buf = AlignedBuf::with_align(self.len, self.align);
+        buf.copy_from_slice(self);
+        buf
+    }
+}
+
+impl Deref for AlignedBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl DerefMut for AlignedBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl Drop for AlignedBuf {
+    fn drop(&mut self) {
+        if self.capacity > 0 {
+            unsafe {
+                let layout = Layout::from_size_align_unchecked(
+                    self.capacity, self.align);
+                dealloc(self.ptr.as_ptr(), layout);
+            }
+        }
+    }
+}
+
+// Safety: the buffer is exclusively owned, like a `Box<[u8]>`.
+unsafe impl Send for AlignedBuf {}
+unsafe impl Sync for AlignedBuf {}
+
+impl fmt::Debug for AlignedBuf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AlignedBuf")
+            .field("ptr", &self.ptr)
+            .field("len", &self.len)
+            .field("align", &self.align)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AlignedBuf;
+    use crate::mem::{page_size, MemAdvice, MemHandle, MemHandleMut};
+
+    #[test]
+    fn test_aligned() {
+        let ps = page_size();
+        let buf = AlignedBuf::new(ps + 1);
+        assert_eq!(buf.len(), ps + 1);
+        assert_eq!(buf.capacity(), 2 * ps);
+        assert_eq!(buf.as_ptr() as usize % ps, 0);
+        assert!(buf.iter().all(|&b| b == 0));
+
+        let buf = AlignedBuf::huge(10);
+        assert_eq!(buf.as_ptr() as usize % buf.align(), 0);
+        assert_eq!(buf.capacity(), buf.align());
+
+        let buf = AlignedBuf::new(0);
+        assert!(buf.is_empty());
+        assert_eq!(buf.capacity(), 0);
+        assert_eq!(&buf[..], &[] as &[u8]);
+    }
+
+    #[test]
+    fn test_from_clone() {
+        let buf = AlignedBuf::from(&b"aligned"[..]);
+        let mut copy = buf.clone();
+        copy[0] = b'A';
+        assert_eq!(&buf[..], b"aligned");
+        assert_eq!(&copy[..], b"Aligned");
+        assert_eq!(copy.as_ptr() as usize % page_size(), 0);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_advise() {
+        let ps = page_size();
+        let mut h = MemHandleMut::new(AlignedBuf::new(4 * ps + 7));
+        h[0] = 1;
+        assert_eq!(h.advise(MemAdvice::Random).unwrap(), MemAdvice::Random);
+        assert_eq!(h.advise_range(ps..(2 * ps), MemAdvice::Sequential)
+                   .unwrap(), MemAdvice::Sequential);
+        let h = h.into_handle();
+        assert_eq!(h.advise(MemAdvice::WillNeed).unwrap(),
+                   MemAdvice::WillNeed);
+        assert_eq!(h[0], 1);
+
+        let h = MemHandle::new(AlignedBuf::huge(ps));
+        assert_eq!(h.advise_range(0..ps, MemAdvice::Random).unwrap(),
+                   MemAdvice::Random);
+        assert_eq!(h.advice_snapshot().range_segments(), 1);
+        assert!(h.advice_snapshot().last_error().is_none());
+    }
+}
@@ -0,0 +1,158 @@
+use std::fs::File;
+use std::io;
+use std::sync::{Mutex, MutexGuard};
+
+use crate::mem::{MapOptions, MappedSlice, MemHandle};
+
+/// A read-only memory map of a file which may grow, for example an
+/// append-only data file, remapped on request to follow the file length.
+///
+/// Readers obtain a [`MemHandle`] to the current mapping via
+/// [`GrowableMap::handle`]. On [`GrowableMap::refresh`], if the file has
+/// grown, a new mapping of the full length is created and swapped in for
+/// subsequent readers. Existing handles continue to pin the old mapping,
+/// which is only unmapped once the last handle to it is dropped. The new
+/// mapping is created with `mmap` rather than extending the old via Linux
+/// `mremap`, since the latter may move the mapping, invalidating outstanding
+/// references to it.
+///
+/// As with any memory map, truncating the file while mapped is not
+/// supported, and may result in a bus error (`SIGBUS`) when accessing pages
+/// beyond the new end of the file. The length is never reduced on refresh.
+///
+/// This requires the _mmap_ feature.
+#[derive(Debug)]
+pub struct GrowableMap {
+    file: File,
+    options: MapOptions,
+    current: Mutex<MemHandle<MappedSlice>>,
+}
+
+impl GrowableMap {
+    /// Map the file for its current length, read-only.
+    pub fn new(file: File) -> io::Result<GrowableMap> {
+        GrowableMap::with_options(file, MapOptions::new())
+    }
+
+    /// Map the file for its current length, read-only, with the given
+    /// options applied to this and each subsequent mapping.
+    pub fn with_options(file: File, options: MapOptions)
+        -> io::Result<GrowableMap>
+    {
+        let len = file.metadata()?.len();
+        let current = Mutex::new(map(&file, &options, len)?);
+        Ok(GrowableMap { file, options, current })
+    }
+
+    /// Return a new handle to the current mapping. Like a clone, this
+    /// starts with the implicit `MemAdvice::Normal`, and pins the mapping
+    /// until dropped, independent of any later refresh.
+    pub fn handle(&self) -> MemHandle<MappedSlice> {
+        self.lock_current().clone()
+    }
+
+    /// Return the length in bytes of the current mapping.
+    pub fn len(&self) -> usize {
+        self.lock_current().len()
+    }
+
+    /// Return true if the current mapping is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Return a reference to the underlying file.
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+
+    /// Remap if the file has grown beyond the current mapping. Returns true
+    /// if a new mapping was swapped in.
+    pub fn refresh(&self) -> io::Result<bool> {
+        let len = self.file.metadata()?.len();
+        let mut current = self.lock_current();
+        if len <= current.len() as u64 {
+            return Ok(false);
+        }
+        *current = map(&self.file, &self.options, len)?;
+        Ok(true)
+    }
+
+    fn lock_current(&self) -> MutexGuard<'_, MemHandle<MappedSlice>> {
+        // Recover from poisoning, as the handle is only ever replaced whole
+        self.current.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn map(file: &File, options: &MapOptions, len: u64)
+    -> io::Result<MemHandle<MappedSlice>>
+{
+    options.handle(MappedSlice::map(file, 0, len)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::thread;
+
+    use tempfile::tempfile;
+
+    use super::GrowableMap;
+    use crate::mem::{MapOptions, MemAdvice};
+
+    #[test]
+    fn test_refresh() {
+        let mut f = tempfile().unwrap();
+        f.write_all(b"first").unwrap();
+        let g = GrowableMap::new(f.try_clone().unwrap()).unwrap();
+        let h1 = g.handle();
+        assert_eq!(&h1[..], b"first");
+        assert!(!g.refresh().unwrap());
+
+        f.write_all(b" second").unwrap();
+        assert_eq!(g.len(), 5);
+        assert!(g.refresh().unwrap());
+        assert_eq!(g.len(), 12);
+
+        let h2 = g.handle();
+        assert_eq!(&h2[..], b"first second");
+        // The old mapping remains pinned and valid
+        assert_eq!(&h1[..], b"first");
+        assert_eq!(h1.advise(MemAdvice::Random).unwrap(), MemAdvice::Random);
+        assert_eq!(h2.advise(MemAdvice::Sequential).unwrap(),
+                   MemAdvice::Sequential);
+        drop(g);
+        assert_eq!(&h2[..], b"first second");
+    }
+
+    #[test]
+    fn test_empty() {
+        let mut f = tempfile().unwrap();
+        let g = GrowableMap::with_options(
+            f.try_clone().unwrap(),
+            MapOptions::new().populate()
+        ).unwrap();
+        assert!(g.is_empty());
+        assert!(g.handle().is_empty());
+
+        f.write_all(&vec![7u8; 10_000]).unwrap();
+        assert!(g.refresh().unwrap());
+        assert_eq!(g.handle()[9_999], 7);
+    }
+
+    #[test]
+    fn test_threaded() {
+        let mut f = tempfile().unwrap();
+        f.write_all(&[1u8; 4096]).unwrap();
+        let g = GrowableMap::new(f.try_clone().unwrap()).unwrap();
+        let h = g.handle();
+        let reader = thread::spawn(move || {
+            h.iter().map(|&b| b as usize).sum::<usize>()
+        });
+        f.write_all(&[2u8; 4096]).unwrap();
+        g.refresh().unwrap();
+        assert_eq!(reader.join().unwrap(), 4096);
+        assert_eq!(g.handle().iter().map(|&b| b as usize).sum::<usize>(),
+                   3 * 4096);
+    }
+}
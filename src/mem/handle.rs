@@ -1,40 +1,227 @@
+use std::collections::HashMap;
 use std::fmt;
 use std::io;
-use std::ops::Deref;
+use std::ops::{Deref, DerefMut, Range};
 use std::sync::atomic::{
     AtomicU64,
     Ordering::{Acquire, SeqCst},
 };
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+
+#[cfg(unix)]
+use crate::mem::page::{page_inner, page_outer};
+use crate::mem::page_size;
+use crate::mem::lock::{mlock, munlock, MemLockError};
+use crate::mem::prefetch::{self, Prefetch};
+use crate::mem::ranges::Segments;
+
+#[cfg(feature = "mmap")]
+use crate::mem::MappedSliceMut;
+
+#[cfg(all(feature = "linux-advice",
+          any(target_os = "linux", target_os = "android")))]
+use crate::mem::{linux, LinuxAdvice};
 
 // Prefer a u64 representation of advice on all platforms, as it affords room
-// for 6 advise levels above baseline (currently `Normal`). Of course, usize is
-// already 64 bit unsigned on platforms like x86_64.
+// for 5 advise levels above baseline (`Normal`), with 12-bit advisor counts
+// each. Of course, usize is already 64 bit unsigned on platforms like x86_64.
+
+// Maximum packed advisor count per level. Beyond this, counts overflow to a
+// (locked) secondary counter.
+const COUNT_MAX: u64 = 0xFFF;
+
+// The per-handle state is also packed in a u64: bits 1-8 are the level of
+// the handle's MemAdvice (0 for `Normal` or a `LEVELS` index + 1), bits 9-16
+// are reserved for other per-handle flags, e.g. `LinuxAdvice`, and bits
+// 33-64 are an id for any range advice, assigned on first use (0 is none).
+const LEVEL_MASK: u64 = 0xFF;
+const ID_SHIFT: u32 = 32;
 
 /// Possible error with `libc::(posix_)madvise()`, or other platform
-/// equivalent.
+/// equivalent, or when advice could not be counted.
 ///
-/// Implements `std::error::Error` and may be converted to an
-/// `io::Error(Other)`.
-#[derive(Debug)]
+/// Carries the failing advice and the region of the underlying buffer it was
+/// given for. Implements `std::error::Error` and may be converted to an
+/// `io::Error`, of a kind mapped from the operating system error code (see
+/// [`MemAdviseError::kind`]).
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct MemAdviseError {
     ecode: i32,
+    saturated: bool,
+    advice: Option<MemAdvice>,
+    #[cfg(all(feature = "linux-advice",
+              any(target_os = "linux", target_os = "android")))]
+    linux: Option<LinuxAdvice>,
+    region: Range<usize>,
 }
 
 impl From<MemAdviseError> for io::Error {
     fn from(me: MemAdviseError) -> io::Error {
-        io::Error::new(io::ErrorKind::Other, me)
+        io::Error::new(me.kind(), me)
     }
 }
 
 impl fmt::Display for MemAdviseError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "libc::posix_madvise error return code {}", self.ecode)
+        #[cfg(all(feature = "linux-advice",
+                  any(target_os = "linux", target_os = "android")))]
+        {
+            if let Some(linux) = self.linux {
+                if self.saturated {
+                    return write!(
+                        f, "LinuxAdvice {:?} could not be counted, \
+                            requesters saturated", linux);
+                }
+                return write!(
+                    f, "libc::madvise {:?} for region {:?}, error return \
+                        code {}", linux, self.region, self.ecode);
+            }
+        }
+        if self.saturated {
+            return write!(
+                f, "MemAdvice {:?} could not be counted, advisors saturated",
+                self.advice.unwrap_or(MemAdvice::Normal));
+        }
+        match self.advice {
+            Some(advice) => write!(
+                f, "libc::(posix_)madvise {:?} for region {:?}, error return \
+                    code {}", advice, self.region, self.ecode),
+            None => write!(
+                f, "libc::(posix_)madvise error return code {}", self.ecode),
+        }
     }
 }
 
 impl std::error::Error for MemAdviseError {}
 
+impl MemAdviseError {
+    #[cfg(unix)]
+    pub(crate) fn new(ecode: i32) -> MemAdviseError {
+        MemAdviseError {
+            ecode,
+            saturated: false,
+            advice: None,
+            #[cfg(all(feature = "linux-advice",
+                      any(target_os = "linux", target_os = "android")))]
+            linux: None,
+            region: 0..0,
+        }
+    }
+
+    fn saturated() -> MemAdviseError {
+        MemAdviseError {
+            ecode: 0,
+            saturated: true,
+            advice: None,
+            #[cfg(all(feature = "linux-advice",
+                      any(target_os = "linux", target_os = "android")))]
+            linux: None,
+            region: 0..0,
+        }
+    }
+
+    // Attach the failing advice and region of the buffer.
+    fn at(mut self, advice: MemAdvice, region: Range<usize>)
+        -> MemAdviseError
+    {
+        self.advice = Some(advice);
+        self.region = region;
+        self
+    }
+
+    // Attach the failing Linux-specific advice and region of the buffer.
+    #[cfg(all(feature = "linux-advice",
+              any(target_os = "linux", target_os = "android")))]
+    fn at_linux(mut self, advice: LinuxAdvice, region: Range<usize>)
+        -> MemAdviseError
+    {
+        self.linux = Some(advice);
+        self.region = region;
+        self
+    }
+
+    /// Return the advice which failed, or `None` for `LinuxAdvice`.
+    pub fn advice(&self) -> Option<MemAdvice> {
+        self.advice
+    }
+
+    /// Return the Linux-specific advice which failed, if applicable.
+    #[cfg(all(feature = "linux-advice",
+              any(target_os = "linux", target_os = "android")))]
+    pub fn linux_advice(&self) -> Option<LinuxAdvice> {
+        self.linux
+    }
+
+    /// Return the region of the underlying buffer, as byte offsets, for
+    /// which the advice failed. This is relative to the start of the
+    /// buffer, not that of a slice handle.
+    pub fn region(&self) -> Range<usize> {
+        self.region.clone()
+    }
+
+    /// Return the `io::ErrorKind` for the operating system error code, for
+    /// example `InvalidInput` for `EINVAL`, or `Other` if saturated.
+    pub fn kind(&self) -> io::ErrorKind {
+        if self.saturated {
+            io::ErrorKind::Other
+        } else {
+            io::Error::from_raw_os_error(self.ecode).kind()
+        }
+    }
+
+    /// Return true if this error is due to advice which could not be
+    /// counted, because the advisor count for the level is exhausted. The
+    /// handle's advice then reverts to `MemAdvice::Normal`. For a
+    /// `LinuxAdvice` flag, the requester count is exhausted, and the flag is
+    /// not requested via the handle.
+    pub fn is_saturated(&self) -> bool {
+        self.saturated
+    }
+
+    /// Return the operating system error code, if applicable.
+    pub fn raw_os_error(&self) -> Option<i32> {
+        if self.saturated { None } else { Some(self.ecode) }
+    }
+}
+
+/// Policy for errors relaying advice to the operating system, shared by all
+/// handles to the same memory, and set via [`MemHandle::set_advice_policy`].
+///
+/// As advice is only a hint, its failure may often be safely ignored. The
+/// policy applies to errors from the underlying system calls, including
+/// those on drop of handles and guards, which can't otherwise be returned.
+/// Saturated errors (see [`MemAdviseError::is_saturated`]) are always
+/// returned, as the advice was not counted. All errors are recorded as the
+/// last error (see [`AdviceSnapshot::last_error`]), regardless of policy.
+#[derive(Clone)]
+pub enum AdvicePolicy {
+    /// Ignore errors.
+    Ignore,
+    /// Pass each error to the given hook, for example for logging, and
+    /// otherwise ignore it. The hook is called without any internal locks
+    /// held, so may use (or drop) handles to the same memory.
+    Log(Arc<dyn Fn(&MemAdviseError) + Send + Sync>),
+    /// Return errors from advice methods (default). Errors on drop are only
+    /// recorded.
+    Strict,
+}
+
+impl Default for AdvicePolicy {
+    fn default() -> AdvicePolicy {
+        AdvicePolicy::Strict
+    }
+}
+
+impl fmt::Debug for AdvicePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AdvicePolicy::Ignore => f.write_str("Ignore"),
+            AdvicePolicy::Log(_) => f.write_str("Log(..)"),
+            AdvicePolicy::Strict => f.write_str("Strict"),
+        }
+    }
+}
+
 /// Memory access pattern advice.
 ///
 /// This encodes a subset of POSIX.1-2001 `madvise` flags, and is intending to
@@ -42,21 +229,92 @@ impl std::error::Error for MemAdviseError {}
 /// correspond to any libc or other lib constants, and are arranged in
 /// ascending order of minimal to maximum *priority* in the presence of
 /// concurrent interest in the same region.
+///
+/// `Random` and `Sequential` are _persistent_ access patterns: once relayed
+/// to the operating system, these remain in effect for the region until
+/// other advice is relayed. `WillNeed` and `DontNeed` are _one-shot_ actions
+/// (read-ahead, or release of pages) with no persistent effect. In
+/// the [`MemHandle`] concurrent advisor model, all levels are counted and
+/// prioritized alike, but the persistent pattern is arbitrated separately
+/// from one-shot advice, so that one-shot advice never masks it:
+///
+/// * One-shot advice is relayed when it becomes the highest priority advice,
+///   and is relayed again on each repeated `advise` call with the same
+///   advice, while it remains the highest priority.
+///
+/// * The persistent pattern relayed is the highest priority of `Normal`,
+///   `Random` and `Sequential` advice, independent of any one-shot advice.
+///
+/// The eviction advice, `DontNeed`, is of lower priority than any access
+/// pattern, and is thus only relayed when no other surviving handle advises
+/// `Random`, `Sequential` or `WillNeed`. `WillNeed` is the highest priority,
+/// as read-ahead is harmless to all other advisors.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 #[repr(u64)]
 pub enum MemAdvice {
-    Normal     = 0,       // Not counted
-    Random     = 0x003FF, // Bits  1-10 mask value
-    Sequential = 0xFFC00, // Bits 11-20 mask value
+    /// No special treatment (baseline). Not counted.
+    Normal     = 0,
+    /// Pages are not expected to be accessed in the near future. This is
+    /// relayed as `posix_madvise(POSIX_MADV_DONTNEED)`, which is advisory
+    /// only: content is always preserved, and some platforms (e.g. Linux)
+    /// treat it as a no-op. The destructive Linux `madvise(MADV_DONTNEED)`
+    /// or `MADV_FREE` is never used, as other handles may still be reading
+    /// the memory.
+    DontNeed   = 0x0000_0000_0000_0FFF, // Bits  1-12 mask value
+    /// Expect page references in random order.
+    Random     = 0x0000_0000_00FF_F000, // Bits 13-24 mask value
+    /// Expect page references in sequential order.
+    Sequential = 0x0000_000F_FF00_0000, // Bits 25-36 mask value
+    /// Expect access in the near future, so start read-ahead.
+    WillNeed   = 0x0000_FFF0_0000_0000, // Bits 37-48 mask value
+}
+
+// Counted advice levels in ascending priority order.
+const LEVELS: [MemAdvice; 4] = [
+    MemAdvice::DontNeed,
+    MemAdvice::Random,
+    MemAdvice::Sequential,
+    MemAdvice::WillNeed,
+];
+
+impl MemAdvice {
+    // Return the level of this advice, for the per-handle state.
+    fn level(self) -> u64 {
+        match self {
+            MemAdvice::Normal => 0,
+            _ => u64::from(shift(self) / 12) + 1,
+        }
+    }
+
+    // Return advice from the level of the per-handle state.
+    fn from_level(level: u64) -> MemAdvice {
+        match level {
+            0 => MemAdvice::Normal,
+            l => LEVELS[(l - 1) as usize],
+        }
+    }
+
+    // Return true if one-shot advice, without persistent effect.
+    fn is_one_shot(self) -> bool {
+        match self {
+            MemAdvice::Normal     |
+            MemAdvice::Random     |
+            MemAdvice::Sequential => false,
+            MemAdvice::DontNeed   |
+            MemAdvice::WillNeed   => true,
+        }
+    }
 }
 
 impl From<u64> for MemAdvice {
     fn from(v: u64) -> Self {
         match v {
-            0       => MemAdvice::Normal,
-            0x003FF => MemAdvice::Random,
-            0xFFC00 => MemAdvice::Sequential,
-            _       => unreachable!("not a MemAdvice repr!"),
+            0                     => MemAdvice::Normal,
+            0x0000_0000_0000_0FFF => MemAdvice::DontNeed,
+            0x0000_0000_00FF_F000 => MemAdvice::Random,
+            0x0000_000F_FF00_0000 => MemAdvice::Sequential,
+            0x0000_FFF0_0000_0000 => MemAdvice::WillNeed,
+            _                     => unreachable!("not a MemAdvice repr!"),
         }
     }
 }
@@ -71,16 +329,29 @@ impl From<u64> for MemAdvice {
 /// inexpensive. Each new and cloned handle starts with the implicit
 /// `MemAdvice::Normal`. The shared state, a list of advisor interest counts,
 /// is maintained as a single atomic integer, for minimal overhead. Each
-/// `MemAdvice` level is allocated 10-bits or up to 1,023 MemHandle
-/// advisors. Any advice beyond this capacity or after an error is returned
+/// `MemAdvice` level is allocated 12-bits or up to 4,095 MemHandle
+/// advisors. Beyond this capacity, the level's count is saturated and
+/// additional advisors are counted via a secondary, locked overflow counter,
+/// so advice is not dropped. In the unlikely event that the overflow counter
+/// is also exhausted, `advise` returns a saturated `MemAdviseError` (see
+/// [`MemAdviseError::is_saturated`]). Any advice after an error is returned
 /// from `advise`, may be ignored, favoring the prior highest priority
 /// advice.
+///
+/// Advice may also be given for sub-ranges of the buffer, via
+/// [`MemHandle::advise_range`]. Range advice is tracked per page region in a
+/// shared table (under a mutex), which is only consulted when the effective
+/// advice changes. Handles to disjoint (or overlapping) sub-ranges of the same
+/// buffer may be obtained via [`MemHandle::slice`] and
+/// [`MemHandle::split_at`], each advising only for its own region.
 #[derive(Debug)]
 pub struct MemHandle<T>
     where T: Deref<Target=[u8]>
 {
     mem: Arc<Mem<T>>,
-    advice: AtomicU64,
+    state: AtomicU64,
+    offset: usize,
+    len: usize,
 }
 
 impl<T> MemHandle<T>
@@ -90,38 +361,506 @@ impl<T> MemHandle<T>
     /// references to the underlying buffer can then be created by `clone` of
     /// this handle.
     pub fn new(mem: T) -> MemHandle<T> {
+        let len = mem.len();
         MemHandle {
             mem: Arc::new(Mem::new(mem)),
-            advice: AtomicU64::new(MemAdvice::Normal as u64)
+            state: AtomicU64::new(0),
+            offset: 0,
+            len,
         }
     }
 
+    /// Return a new handle to a sub-range of this handle's buffer, sharing
+    /// the same underlying memory. Like a clone, the new handle starts with
+    /// the implicit `MemAdvice::Normal`, and dereferences to only its
+    /// sub-range. Advice via a slice handle applies only to its region, as
+    /// range advice (see [`MemHandle::advise_range`]).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is out of bounds of this handle's buffer.
+    pub fn slice(&self, range: Range<usize>) -> MemHandle<T> {
+        assert!(range.start <= range.end && range.end <= self.len,
+                "slice {:?} out of bounds", range);
+        MemHandle {
+            mem: self.mem.clone(),
+            state: AtomicU64::new(0),
+            offset: self.offset + range.start,
+            len: range.end - range.start,
+        }
+    }
+
+    /// Split into two new handles, for `[0, mid)` and `[mid, len)`, as per
+    /// [`MemHandle::slice`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `mid > len`.
+    pub fn split_at(&self, mid: usize) -> (MemHandle<T>, MemHandle<T>) {
+        assert!(mid <= self.len, "split_at {} out of bounds", mid);
+        (self.slice(0..mid), self.slice(mid..self.len))
+    }
+
     /// Advise on access plans for the underlying memory. There may be
     /// multiple cloned handles to the same memory region, so the advice is
     /// only relayed to the operating system if it has greater priority than
     /// any other advice made via another surviving handle. On success,
     /// returns the MemAdvice as relayed, or a snapshot of the current,
     /// highest priority advice. Returns an error if the underlying system
-    /// call fails.
+    /// call fails, subject to the [`AdvicePolicy`].
+    ///
+    /// For a handle to a sub-range, via [`MemHandle::slice`], this is
+    /// equivalent to `advise_range` over the entire sub-range, and replaces
+    /// any prior range advice via this handle.
     pub fn advise(&self, advice: MemAdvice)
         -> Result<MemAdvice, MemAdviseError>
     {
-        let prior = self.advice.swap(advice as u64, SeqCst).into();
-        if advice == prior {
+        let prior = self.swap_advice(advice);
+        self.adjust_advice(prior, advice)
+    }
+
+    /// Advise on access plans for the underlying memory, as per `advise`,
+    /// returning a guard which restores this handle's prior advice when
+    /// dropped. This is convenient for temporary access patterns, such as a
+    /// sequential scan. Guards may be nested, and should then be dropped in
+    /// reverse order. On error, the prior advice is restored before
+    /// returning.
+    pub fn advise_scoped(&self, advice: MemAdvice)
+        -> Result<AdviceGuard<'_, T>, MemAdviseError>
+    {
+        let prior = self.swap_advice(advice);
+        match self.adjust_advice(prior, advice) {
+            Ok(effective) => {
+                Ok(AdviceGuard { handle: self, prior, effective })
+            }
+            Err(e) => {
+                self.advise(prior).ok();
+                Err(e)
+            }
+        }
+    }
+
+    // Count the entire buffer as locked, as already done via
+    // `MapOptions::lock`.
+    #[cfg(feature = "mmap")]
+    pub(crate) fn hold_lock(&self) {
+        self.mem.hold_lock()
+    }
+
+    /// Lock the pages of a range of this handle's buffer in RAM (via
+    /// `mlock`), preventing these from being paged out, until the returned
+    /// guard is dropped. Locks are reference counted per page across all
+    /// handles to the same memory, so a page is only unlocked (via
+    /// `munlock`) when the last guard covering it is dropped. Memory locked
+    /// via `MapOptions::lock` remains locked until the memory is dropped.
+    /// The guard borrows this handle, so isn't counted as a handle, for
+    /// example by [`MemHandle::advice_snapshot`] or
+    /// [`MemHandle::try_into_mut`].
+    ///
+    /// The range is extended to whole pages, and an empty range locks
+    /// nothing. Returns an error if locking fails, which is distinguished
+    /// when due to the `RLIMIT_MEMLOCK` resource limit, or on non-unix
+    /// platforms.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is out of bounds of the buffer.
+    pub fn lock(&self, range: Range<usize>)
+        -> Result<LockGuard<'_, T>, MemLockError>
+    {
+        assert!(range.start <= range.end && range.end <= self.len,
+                "lock {:?} out of bounds", range);
+        let range = (self.offset + range.start)..(self.offset + range.end);
+        // Page-aligned superset, as with non-eviction advice
+        let (start, end) = page_range(&self.mem, range, MemAdvice::Normal);
+        self.mem.lock(start, end)?;
+        Ok(LockGuard { handle: self, start, end })
+    }
+
+    /// Return a point-in-time snapshot of the advice state shared by all
+    /// handles to the underlying memory, for logging or metrics.
+    pub fn advice_snapshot(&self) -> AdviceSnapshot {
+        let mut snap = self.mem.snapshot();
+        snap.handles = Arc::strong_count(&self.mem);
+        snap
+    }
+
+    /// Return a weak reference to this handle's buffer, which doesn't keep
+    /// it alive, for example for a cache. A weak handle doesn't participate
+    /// in advice, and isn't counted in [`AdviceSnapshot::handles`]. While
+    /// any weak handle exists, [`MemHandle::get_mut`] returns `None`.
+    pub fn downgrade(&self) -> WeakMemHandle<T> {
+        WeakMemHandle {
+            mem: Arc::downgrade(&self.mem),
+            offset: self.offset,
+            len: self.len,
+        }
+    }
+
+    /// Set the policy for errors relaying advice, shared by all handles to
+    /// the underlying memory. The default is `AdvicePolicy::Strict`.
+    pub fn set_advice_policy(&self, policy: AdvicePolicy) {
+        *self.mem.lock_policy() = policy;
+    }
+
+    /// Return the policy for errors relaying advice.
+    pub fn advice_policy(&self) -> AdvicePolicy {
+        self.mem.lock_policy().clone()
+    }
+
+    // Adjust shared state for a change in this handle's advice, relaying
+    // as needed.
+    fn adjust_advice(&self, prior: MemAdvice, advice: MemAdvice)
+        -> Result<MemAdvice, MemAdviseError>
+    {
+        if !self.is_whole() {
+            self.advise_range(0..self.len, advice)
+        } else if advice != prior {
+            let res = self.mem.adjust_advice(prior, advice);
+            if let Err(ref e) = res {
+                if e.is_saturated() {
+                    self.revert_advice(advice);
+                }
+            }
+            res
+        } else if advice.is_one_shot() {
+            self.mem.repeat_advice(advice)
+        } else {
             Ok(prior)
+        }
+    }
+
+    // Return true if this handle covers the entire underlying buffer.
+    fn is_whole(&self) -> bool {
+        self.offset == 0 && self.len == self.mem.len()
+    }
+
+    /// Advise on access plans for a range of this handle's buffer. This is
+    /// tracked separately from advice for the entire buffer, via `advise`,
+    /// and replaces any prior range advice made via this handle for the same
+    /// pages. `MemAdvice::Normal` withdraws prior range advice. The effective
+    /// advice for each page is the highest priority of all advice
+    /// overlapping it, from any surviving handle, and advice is only relayed
+    /// to the operating system for the regions where the effective advice
+    /// changes.
+    ///
+    /// The range is extended to whole pages, or for eviction advice
+    /// (`DontNeed`), reduced to the whole pages it contains. On
+    /// success, returns the highest priority effective advice over the
+    /// range. Returns an error if an underlying system call fails.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is out of bounds of the buffer.
+    pub fn advise_range(&self, range: Range<usize>, advice: MemAdvice)
+        -> Result<MemAdvice, MemAdviseError>
+    {
+        assert!(range.start <= range.end && range.end <= self.len,
+                "advise_range {:?} out of bounds", range);
+        let range = (self.offset + range.start)..(self.offset + range.end);
+        let (start, end) = page_range(&self.mem, range, advice);
+        let mut errors = Vec::new();
+        let top = {
+            let mut ranges = self.mem.lock_ranges();
+            let mut state = self.state.load(Acquire);
+            if state >> ID_SHIFT == 0 {
+                let id = ranges.next_id();
+                state = self.state.fetch_or(id << ID_SHIFT, SeqCst);
+                debug_assert_eq!(state >> ID_SHIFT, 0);
+                state |= id << ID_SHIFT;
+            }
+            let id = state >> ID_SHIFT;
+            self.mem.advise_range(
+                &mut ranges, id, start, end, advice, &mut errors)
+        };
+        self.mem.apply_policy(errors)?;
+        Ok(top)
+    }
+
+    // Advice was not counted, so revert this handle to Normal, unless its
+    // advice has since changed.
+    fn revert_advice(&self, advice: MemAdvice) {
+        let mut state = self.state.load(Acquire);
+        while state & LEVEL_MASK == advice.level() {
+            match self.state.compare_exchange_weak(
+                state, state & !LEVEL_MASK, SeqCst, Acquire)
+            {
+                Ok(_) => break,
+                Err(x) => state = x
+            }
+        }
+    }
+
+    // Swap in new advice for this handle, retaining any other state, and
+    // return the prior advice.
+    fn swap_advice(&self, advice: MemAdvice) -> MemAdvice {
+        let mut state = self.state.load(Acquire);
+        loop {
+            let new_state = (state & !LEVEL_MASK) | advice.level();
+            match self.state.compare_exchange_weak(
+                state, new_state, SeqCst, Acquire)
+            {
+                Ok(_) => return MemAdvice::from_level(state & LEVEL_MASK),
+                Err(x) => state = x
+            }
+        }
+    }
+}
+
+#[cfg(all(feature = "linux-advice",
+          any(target_os = "linux", target_os = "android")))]
+impl<T> MemHandle<T>
+    where T: Deref<Target=[u8]>
+{
+    /// Request Linux-specific advice for the underlying memory.
+    ///
+    /// The `HugePage` and `DontDump` flags are reference counted across
+    /// cloned handles: the flag is relayed when first requested via any
+    /// surviving handle, and cleared only once every requesting handle has
+    /// called `clear_linux` or been dropped. Requesting a flag already
+    /// requested via this handle is a no-op. Flags apply to the entire
+    /// underlying buffer, even via a slice handle. The one-shot `Cold` and
+    /// `PageOut` advice is relayed on each call, for only the handle's
+    /// region. Returns an error if the underlying system call fails, or if
+    /// the flag's requester count is saturated, in which case the flag is
+    /// not requested via this handle.
+    pub fn advise_linux(&self, advice: LinuxAdvice)
+        -> Result<(), MemAdviseError>
+    {
+        match advice.flag_bit() {
+            Some(bit) => {
+                if self.state.fetch_or(bit, SeqCst) & bit == 0 {
+                    let res = self.mem.incr_flag(advice);
+                    if let Err(ref e) = res {
+                        if e.is_saturated() {
+                            self.state.fetch_and(!bit, SeqCst);
+                        }
+                    }
+                    res
+                } else {
+                    Ok(())
+                }
+            }
+            None => {
+                let region = self.offset..(self.offset + self.len);
+                let res = linux::advise(self, advice, true);
+                self.mem.report(res.map_err(|e| e.at_linux(advice, region)))
+            }
+        }
+    }
+
+    /// Withdraw a `LinuxAdvice` flag requested via this handle. The flag is
+    /// cleared if no other surviving handle has requested it. This is a
+    /// no-op for one-shot advice, or if the flag was not requested via this
+    /// handle.
+    pub fn clear_linux(&self, advice: LinuxAdvice)
+        -> Result<(), MemAdviseError>
+    {
+        match advice.flag_bit() {
+            Some(bit) if self.state.fetch_and(!bit, SeqCst) & bit != 0 => {
+                self.mem.decr_flag(advice)
+            }
+            _ => Ok(())
+        }
+    }
+}
+
+impl<T> MemHandle<T>
+    where T: Deref<Target=[u8]> + Send + Sync + 'static
+{
+    /// Prefetch a range of this handle's buffer in the background. This
+    /// relays `MemAdvice::WillNeed` for the range, as a one-shot hint which
+    /// doesn't affect other advice, and then touches (reads a byte of) each
+    /// page of the range on a shared background thread, faulting in any
+    /// pages not yet resident. The returned `Prefetch` may be used to
+    /// monitor progress, wait for completion, or cancel. The underlying
+    /// memory is kept alive until the prefetch is finished.
+    ///
+    /// Any error relaying advice is ignored, but is recorded as the last
+    /// error (see [`MemHandle::advice_snapshot`]), and passed to any
+    /// `AdvicePolicy::Log` hook.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is out of bounds of the buffer.
+    pub fn prefetch(&self, range: Range<usize>) -> Prefetch {
+        let slice = self.slice(range);
+        let region = slice.offset..(slice.offset + slice.len);
+        let res = advise(&self.mem[region.clone()], MemAdvice::WillNeed);
+        self.mem.report(res.map_err(|e| e.at(MemAdvice::WillNeed, region)))
+            .ok();
+        prefetch::spawn(slice)
+    }
+}
+
+impl<T> MemHandle<T>
+    where T: DerefMut<Target=[u8]>
+{
+    /// Return a mutable reference to the underlying buffer, if this is the
+    /// only handle to it, or otherwise `None`. This is similar to
+    /// `Arc::get_mut`, and is useful for example with a private, copy-on-write
+    /// memory map.
+    pub fn get_mut(&mut self) -> Option<&mut [u8]> {
+        let (offset, len) = (self.offset, self.len);
+        Arc::get_mut(&mut self.mem).map(|m| &mut m.mem[offset..offset+len])
+    }
+
+    /// Convert to an exclusive, writable `MemHandleMut`, if this is the only
+    /// handle to the buffer, or otherwise return self as the error. Any
+    /// advice given via this handle is retained.
+    pub fn try_into_mut(mut self) -> Result<MemHandleMut<T>, MemHandle<T>> {
+        if Arc::get_mut(&mut self.mem).is_some() {
+            Ok(MemHandleMut { handle: self })
         } else {
-            self.mem.adjust_advice(prior, advice)
+            Err(self)
         }
     }
 }
 
+/// Exclusive, writable wrapper over a byte buffer, supporting the same
+/// memory access advice as [`MemHandle`].
+///
+/// Unlike `MemHandle`, this type can't be cloned, which guarantees safe
+/// exclusive write access via `DerefMut`. Once writes are complete, it may be
+/// converted via [`MemHandleMut::into_handle`] to a `MemHandle` for shared
+/// read access, retaining any advice given. Conversely,
+/// [`MemHandle::try_into_mut`] converts a unique `MemHandle`.
+///
+/// For a writable memory map, flush methods are also provided.
+#[derive(Debug)]
+pub struct MemHandleMut<T>
+    where T: DerefMut<Target=[u8]>
+{
+    handle: MemHandle<T>,
+}
+
+impl<T> MemHandleMut<T>
+    where T: DerefMut<Target=[u8]>
+{
+    /// Wrap an owned instance of a mutable byte slice buffer.
+    pub fn new(mem: T) -> MemHandleMut<T> {
+        MemHandleMut { handle: MemHandle::new(mem) }
+    }
+
+    /// Advise on access plans for the underlying memory, as per
+    /// [`MemHandle::advise`].
+    pub fn advise(&self, advice: MemAdvice)
+        -> Result<MemAdvice, MemAdviseError>
+    {
+        self.handle.advise(advice)
+    }
+
+    /// Advise on access plans for a range of the underlying memory, as per
+    /// [`MemHandle::advise_range`].
+    pub fn advise_range(&self, range: Range<usize>, advice: MemAdvice)
+        -> Result<MemAdvice, MemAdviseError>
+    {
+        self.handle.advise_range(range, advice)
+    }
+
+    /// Request Linux-specific advice for the underlying memory, as per
+    /// [`MemHandle::advise_linux`].
+    #[cfg(all(feature = "linux-advice",
+              any(target_os = "linux", target_os = "android")))]
+    pub fn advise_linux(&self, advice: LinuxAdvice)
+        -> Result<(), MemAdviseError>
+    {
+        self.handle.advise_linux(advice)
+    }
+
+    /// Withdraw a `LinuxAdvice` flag, as per [`MemHandle::clear_linux`].
+    #[cfg(all(feature = "linux-advice",
+              any(target_os = "linux", target_os = "android")))]
+    pub fn clear_linux(&self, advice: LinuxAdvice)
+        -> Result<(), MemAdviseError>
+    {
+        self.handle.clear_linux(advice)
+    }
+
+    /// Advise on access plans for the underlying memory, restoring prior
+    /// advice when the returned guard drops, as per
+    /// [`MemHandle::advise_scoped`].
+    pub fn advise_scoped(&self, advice: MemAdvice)
+        -> Result<AdviceGuard<'_, T>, MemAdviseError>
+    {
+        self.handle.advise_scoped(advice)
+    }
+
+    /// Set the policy for errors relaying advice, as per
+    /// [`MemHandle::set_advice_policy`].
+    pub fn set_advice_policy(&self, policy: AdvicePolicy) {
+        self.handle.set_advice_policy(policy)
+    }
+
+    /// Convert to a shareable, read-only `MemHandle`, retaining any advice
+    /// given via this handle.
+    pub fn into_handle(self) -> MemHandle<T> {
+        self.handle
+    }
+
+    #[cfg(feature = "mmap")]
+    pub(crate) fn hold_lock(&self) {
+        self.handle.hold_lock()
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl MemHandleMut<MappedSliceMut> {
+    /// Flush outstanding modifications to the file (via `msync`), blocking
+    /// until complete.
+    pub fn flush(&self) -> io::Result<()> {
+        self.flush_range(0, self.handle.len)
+    }
+
+    /// Initiate flushing outstanding modifications to the file, without
+    /// waiting for completion. For a sub-range, modifications to the entire
+    /// underlying buffer are flushed.
+    pub fn flush_async(&self) -> io::Result<()> {
+        self.handle.mem.mem.flush_async()
+    }
+
+    /// Flush outstanding modifications in the given range, relative to the
+    /// start of the buffer, blocking until complete. Returns an error if the
+    /// range is out of bounds.
+    pub fn flush_range(&self, offset: usize, len: usize) -> io::Result<()> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.handle.len => {}
+            _ => return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "flush range is out of bounds"
+            )),
+        }
+        self.handle.mem.mem.flush_range(self.handle.offset + offset, len)
+    }
+}
+
+impl<T> Deref for MemHandleMut<T>
+    where T: DerefMut<Target=[u8]>
+{
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.handle
+    }
+}
+
+impl<T> DerefMut for MemHandleMut<T>
+    where T: DerefMut<Target=[u8]>
+{
+    fn deref_mut(&mut self) -> &mut [u8] {
+        self.handle.get_mut().expect("MemHandleMut is not exclusive")
+    }
+}
+
 impl<T> Clone for MemHandle<T>
     where T: Deref<Target=[u8]>
 {
     fn clone(&self) -> MemHandle<T> {
         MemHandle {
             mem: self.mem.clone(),
-            advice: AtomicU64::new(MemAdvice::Normal as u64)
+            state: AtomicU64::new(0),
+            offset: self.offset,
+            len: self.len,
         }
     }
 }
@@ -130,10 +869,24 @@ impl<T> Drop for MemHandle<T>
     where T: Deref<Target=[u8]>
 {
     fn drop(&mut self) {
-        let advice = self.advice.load(Acquire).into();
-        if advice != MemAdvice::Normal {
+        let state = self.state.load(Acquire);
+        let advice = MemAdvice::from_level(state & LEVEL_MASK);
+        if advice != MemAdvice::Normal && self.is_whole() {
             self.mem.adjust_advice(advice, MemAdvice::Normal).ok();
         }
+        let id = state >> ID_SHIFT;
+        if id != 0 {
+            self.mem.release_ranges(id);
+        }
+        #[cfg(all(feature = "linux-advice",
+                  any(target_os = "linux", target_os = "android")))]
+        {
+            for &flag in linux::FLAGS.iter() {
+                if state & flag.flag_bit().unwrap() != 0 {
+                    self.mem.decr_flag(flag).ok();
+                }
+            }
+        }
     }
 }
 
@@ -143,47 +896,738 @@ impl<T> Deref for MemHandle<T>
     type Target = [u8];
 
     fn deref(&self) -> &[u8] {
-        &self.mem
+        &self.mem[self.offset..(self.offset + self.len)]
+    }
+}
+
+impl<T> AsRef<[u8]> for MemHandle<T>
+    where T: Deref<Target=[u8]>
+{
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+/// Zero-copy conversion to `Bytes`, which keeps the underlying memory (e.g.
+/// a memory map) alive until all `Bytes` referencing it are dropped. Any
+/// advice given via the handle is retained until then.
+#[cfg(feature = "bytes")]
+impl<T> From<MemHandle<T>> for bytes::Bytes
+    where T: Deref<Target=[u8]> + Send + Sync + 'static
+{
+    fn from(handle: MemHandle<T>) -> bytes::Bytes {
+        bytes::Bytes::from_owner(handle)
+    }
+}
+
+/// Weak reference to the buffer of a [`MemHandle`], as returned by
+/// [`MemHandle::downgrade`], which doesn't keep the buffer alive.
+#[derive(Debug)]
+pub struct WeakMemHandle<T>
+    where T: Deref<Target=[u8]>
+{
+    mem: Weak<Mem<T>>,
+    offset: usize,
+    len: usize,
+}
+
+impl<T> WeakMemHandle<T>
+    where T: Deref<Target=[u8]>
+{
+    /// Return a new handle to the same buffer (or sub-range), if any
+    /// `MemHandle` to it still exists, or otherwise `None`. Like a clone,
+    /// the new handle starts with the implicit `MemAdvice::Normal`.
+    pub fn upgrade(&self) -> Option<MemHandle<T>> {
+        self.mem.upgrade().map(|mem| MemHandle {
+            mem,
+            state: AtomicU64::new(0),
+            offset: self.offset,
+            len: self.len,
+        })
+    }
+
+    /// Return the length in bytes of the referenced buffer (or sub-range).
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Return true if the referenced buffer (or sub-range) is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<T> Clone for WeakMemHandle<T>
+    where T: Deref<Target=[u8]>
+{
+    fn clone(&self) -> WeakMemHandle<T> {
+        WeakMemHandle {
+            mem: self.mem.clone(),
+            offset: self.offset,
+            len: self.len,
+        }
+    }
+}
+
+/// Guard returned by [`MemHandle::advise_scoped`], which restores the
+/// handle's prior advice when dropped.
+///
+/// Dereferences to the underlying buffer.
+#[must_use = "advice is restored when the guard is dropped"]
+#[derive(Debug)]
+pub struct AdviceGuard<'a, T>
+    where T: Deref<Target=[u8]>
+{
+    handle: &'a MemHandle<T>,
+    prior: MemAdvice,
+    effective: MemAdvice,
+}
+
+impl<'a, T> AdviceGuard<'a, T>
+    where T: Deref<Target=[u8]>
+{
+    /// Return the MemAdvice as relayed, or a snapshot of the highest priority
+    /// advice, at the time the guard was created.
+    pub fn effective(&self) -> MemAdvice {
+        self.effective
+    }
+
+    /// Return the prior advice of the handle, which is restored on drop.
+    pub fn prior(&self) -> MemAdvice {
+        self.prior
+    }
+}
+
+impl<'a, T> Deref for AdviceGuard<'a, T>
+    where T: Deref<Target=[u8]>
+{
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.handle
+    }
+}
+
+impl<'a, T> Drop for AdviceGuard<'a, T>
+    where T: Deref<Target=[u8]>
+{
+    fn drop(&mut self) {
+        // Avoid repeating one-shot advice when restored advice is the same
+        let advice = self.handle.swap_advice(self.prior);
+        if advice != self.prior {
+            self.handle.adjust_advice(advice, self.prior).ok();
+        }
+    }
+}
+
+/// Guard returned by [`MemHandle::lock`], which unlocks the region when
+/// dropped, unless it remains locked by another guard.
+#[must_use = "memory is unlocked when the guard is dropped"]
+#[derive(Debug)]
+pub struct LockGuard<'a, T>
+    where T: Deref<Target=[u8]>
+{
+    handle: &'a MemHandle<T>,
+    start: usize,
+    end: usize,
+}
+
+impl<T> LockGuard<'_, T>
+    where T: Deref<Target=[u8]>
+{
+    /// Return the length of the locked region, in bytes, as extended to
+    /// whole pages.
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    /// Return true if the locked region is empty.
+    pub fn is_empty(&self) -> bool {
+        self.end == self.start
+    }
+}
+
+impl<T> Drop for LockGuard<'_, T>
+    where T: Deref<Target=[u8]>
+{
+    fn drop(&mut self) {
+        self.handle.mem.unlock(self.start, self.end);
+    }
+}
+
+/// A point-in-time snapshot of the advice state shared by all handles to
+/// the same memory, as returned by [`MemHandle::advice_snapshot`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AdviceSnapshot {
+    handles: usize,
+    advisors: [u64; 4],
+    saturated: [bool; 4],
+    effective: MemAdvice,
+    pattern: MemAdvice,
+    range_segments: usize,
+    last_error: Option<MemAdviseError>,
+}
+
+impl AdviceSnapshot {
+    /// Return the number of live handles (including `MemHandleMut`).
+    pub fn handles(&self) -> usize {
+        self.handles
+    }
+
+    /// Return the count of handles advising the given level for the entire
+    /// buffer, including any overflow. `MemAdvice::Normal` is not counted,
+    /// and returns 0.
+    pub fn advisors(&self, advice: MemAdvice) -> u64 {
+        match advice {
+            MemAdvice::Normal => 0,
+            _ => self.advisors[(advice.level() - 1) as usize]
+        }
+    }
+
+    /// Return true if the packed count for the given level is saturated, in
+    /// which case additional advice at this level is counted via the slower
+    /// overflow path.
+    pub fn is_saturated(&self, advice: MemAdvice) -> bool {
+        match advice {
+            MemAdvice::Normal => false,
+            _ => self.saturated[(advice.level() - 1) as usize]
+        }
+    }
+
+    /// Return true if the count for any level is saturated.
+    pub fn any_saturated(&self) -> bool {
+        LEVELS.iter().any(|&l| self.is_saturated(l))
+    }
+
+    /// Return the current highest priority advice for the entire buffer.
+    pub fn effective(&self) -> MemAdvice {
+        self.effective
+    }
+
+    /// Return the current highest priority persistent access pattern
+    /// (`Normal`, `Random` or `Sequential`) for the entire buffer.
+    pub fn pattern(&self) -> MemAdvice {
+        self.pattern
+    }
+
+    /// Return the number of distinct regions with range advice, via
+    /// [`MemHandle::advise_range`].
+    pub fn range_segments(&self) -> usize {
+        self.range_segments
+    }
+
+    /// Return the last error from relaying advice to the operating system,
+    /// including any from dropped handles or guards, if any.
+    pub fn last_error(&self) -> Option<&MemAdviseError> {
+        self.last_error.as_ref()
+    }
+}
+
+#[derive(Debug)]
+struct Mem<T>
+    where T: Deref<Target=[u8]>
+{
+    mem: T,
+    advisors: AtomicU64,
+    overflow: Mutex<[u32; 4]>,
+    ranges: Mutex<Ranges>,
+    locks: Mutex<Segments<u64>>,
+    last_error: Mutex<Option<MemAdviseError>>,
+    policy: Mutex<AdvicePolicy>,
+    #[cfg(all(feature = "linux-advice",
+              any(target_os = "linux", target_os = "android")))]
+    flags: AtomicU64,
+}
+
+impl<T> Mem<T>
+    where T: Deref<Target=[u8]>
+{
+    fn new(mem: T) -> Mem<T> {
+        Mem {
+            mem,
+            advisors: AtomicU64::new(0),
+            overflow: Mutex::new([0; 4]),
+            ranges: Mutex::new(Ranges::default()),
+            locks: Mutex::new(Segments::new()),
+            last_error: Mutex::new(None),
+            policy: Mutex::new(AdvicePolicy::default()),
+            #[cfg(all(feature = "linux-advice",
+                      any(target_os = "linux", target_os = "android")))]
+            flags: AtomicU64::new(0),
+        }
+    }
+
+    fn adjust_advice(&self, prior: MemAdvice, advice: MemAdvice)
+        -> Result<MemAdvice, MemAdviseError>
+    {
+        debug_assert!(prior != advice);
+        let mut adv = self.advisors.load(Acquire);
+        loop {
+            if is_full(adv, prior) || is_full(adv, advice) {
+                return self.adjust_overflow(prior, advice);
+            }
+            let new_adv = decr_advisors(adv, prior);
+            let new_adv = incr_advisors(new_adv, advice);
+            match self.advisors.compare_exchange_weak(
+                adv, new_adv, SeqCst, Acquire)
+            {
+                Ok(_) => {
+                    // Note, may fail after adjustments
+                    return self.relay(adv, new_adv);
+                }
+                Err(x) => adv = x
+            }
+        }
+    }
+
+    // Adjust advice, as per `adjust_advice`, where either level is full,
+    // using the overflow counters. An overflow counter is only non-zero while
+    // the level's packed count is full, so that counts can't leave the full
+    // state without holding the lock.
+    fn adjust_overflow(&self, prior: MemAdvice, advice: MemAdvice)
+        -> Result<MemAdvice, MemAdviseError>
+    {
+        let mut overflow = self.lock_overflow();
+        let mut adv = self.advisors.load(Acquire);
+        loop {
+            let mut new_over = *overflow;
+            let mut new_adv = adv;
+            if prior != MemAdvice::Normal {
+                let i = (prior.level() - 1) as usize;
+                if is_full(adv, prior) && new_over[i] > 0 {
+                    new_over[i] -= 1;
+                } else {
+                    new_adv = decr_advisors(new_adv, prior);
+                }
+            }
+            let mut counted = true;
+            if advice != MemAdvice::Normal {
+                let i = (advice.level() - 1) as usize;
+                if is_full(new_adv, advice) {
+                    match new_over[i].checked_add(1) {
+                        Some(c) => new_over[i] = c,
+                        None => counted = false,
+                    }
+                } else {
+                    new_adv = incr_advisors(new_adv, advice);
+                }
+            }
+            match self.advisors.compare_exchange_weak(
+                adv, new_adv, SeqCst, Acquire)
+            {
+                Ok(_) => {
+                    *overflow = new_over;
+                    drop(overflow);
+                    let res = self.relay(adv, new_adv);
+                    if counted {
+                        return res;
+                    } else {
+                        let e = MemAdviseError::saturated()
+                            .at(advice, 0..self.mem.len());
+                        return self.record(Err(e));
+                    }
+                }
+                Err(x) => adv = x
+            }
+        }
+    }
+
+    // Increment lock counts for the page-aligned region `[start, end)`,
+    // locking any regions not previously locked. On failure, any changes
+    // are reverted.
+    fn lock(&self, start: usize, end: usize) -> Result<(), MemLockError> {
+        let mut locks = self.lock_locks();
+        let changes = locks.update(start, end, |c| c + 1);
+        let mut res = Ok(());
+        let mut locked = Vec::new();
+        for &(s, e, old, _) in &changes {
+            if old == 0 {
+                match mlock(&self.mem[s..e]) {
+                    Ok(()) => locked.push(s..e),
+                    Err(e) => {
+                        res = Err(e);
+                        break;
+                    }
+                }
+            }
+        }
+        if res.is_err() {
+            for r in locked {
+                munlock(&self.mem[r]).ok();
+            }
+            locks.update(start, end, |c| c - 1);
+        }
+        res
+    }
+
+    // Decrement lock counts for the page-aligned region `[start, end)`,
+    // unlocking any regions no longer locked.
+    fn unlock(&self, start: usize, end: usize) {
+        let mut locks = self.lock_locks();
+        for (s, e, _, new) in locks.update(start, end, |c| c - 1) {
+            if new == 0 {
+                munlock(&self.mem[s..e]).ok();
+            }
+        }
+    }
+
+    // Count the entire buffer as locked, as by `MapOptions::lock`, for the
+    // life of the memory, such that it is never unlocked by a guard.
+    #[cfg(feature = "mmap")]
+    fn hold_lock(&self) {
+        let (start, end) = page_range(&self.mem, 0..self.len(),
+                                      MemAdvice::Normal);
+        self.lock_locks().update(start, end, |c| c + 1);
+    }
+
+    fn lock_locks(&self) -> MutexGuard<'_, Segments<u64>> {
+        self.locks.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn lock_overflow(&self) -> MutexGuard<'_, [u32; 4]> {
+        self.overflow.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Relay one-shot advice again where it remains the highest priority.
+    fn repeat_advice(&self, advice: MemAdvice)
+        -> Result<MemAdvice, MemAdviseError>
+    {
+        let adv = self.advisors.load(Acquire);
+        self.relay_all(adv, adv, Some(advice))
+    }
+
+    // Given old and new packed advisors state for the entire buffer, relay
+    // changes, accounting for any range advice. Returns the new highest
+    // priority advice for the entire buffer.
+    fn relay(&self, old_adv: u64, new_adv: u64)
+        -> Result<MemAdvice, MemAdviseError>
+    {
+        self.relay_all(old_adv, new_adv, None)
+    }
+
+    fn relay_all(&self, old_adv: u64, new_adv: u64, repeat: Option<MemAdvice>)
+        -> Result<MemAdvice, MemAdviseError>
+    {
+        let mut errors = Vec::new();
+        {
+            let ranges = self.lock_ranges();
+            for (s, e, c) in ranges.segments.pieces(0, self.mem.len()) {
+                let adv = c.packed();
+                self.relay_piece(s..e, old_adv | adv, new_adv | adv, repeat,
+                                 &mut errors);
+            }
+        }
+        self.apply_policy(errors)?;
+        Ok(top_most(new_adv))
+    }
+
+    // Given old and new packed advisors state for a region, relay any change
+    // in the persistent access pattern, and then any new (or repeat)
+    // highest priority one-shot advice. Returns the new highest priority
+    // advice. Errors are recorded and collected, to be passed to
+    // `apply_policy` once internal locks are released.
+    fn relay_piece(
+        &self,
+        region: Range<usize>,
+        old_adv: u64,
+        new_adv: u64,
+        repeat: Option<MemAdvice>,
+        errors: &mut Vec<MemAdviseError>)
+        -> MemAdvice
+    {
+        let new_pattern = top_pattern(new_adv);
+        if new_pattern != top_pattern(old_adv) {
+            self.relay_advice(region.clone(), new_pattern, errors);
+        }
+        let new_top = top_most(new_adv);
+        if new_top.is_one_shot() &&
+            (new_top != top_most(old_adv) || repeat == Some(new_top))
+        {
+            self.relay_advice(region, new_top, errors);
+        }
+        new_top
+    }
+
+    // Relay advice for a region of the buffer, recording and collecting any
+    // error.
+    fn relay_advice(
+        &self,
+        region: Range<usize>,
+        advice: MemAdvice,
+        errors: &mut Vec<MemAdviseError>)
+    {
+        let res = advise(&self.mem[region.clone()], advice);
+        if let Err(e) = self.record(res.map_err(|e| e.at(advice, region))) {
+            errors.push(e);
+        }
+    }
+
+    // Record any error from relaying advice, and apply the error policy,
+    // only returning the error if strict. As with `apply_policy`, this must
+    // be called without internal locks held.
+    fn report(&self, res: Result<(), MemAdviseError>)
+        -> Result<(), MemAdviseError>
+    {
+        match self.record(res) {
+            Ok(()) => Ok(()),
+            Err(e) => self.apply_policy(vec![e]),
+        }
+    }
+
+    // Apply the error policy to errors already recorded, returning the
+    // first if strict. This must be called without internal locks held, as
+    // a `Log` hook may use (or drop) handles to the same memory.
+    fn apply_policy(&self, errors: Vec<MemAdviseError>)
+        -> Result<(), MemAdviseError>
+    {
+        if errors.is_empty() {
+            return Ok(());
+        }
+        // Clone the policy, so the hook is called without its lock
+        let policy = self.lock_policy().clone();
+        match policy {
+            AdvicePolicy::Ignore => Ok(()),
+            AdvicePolicy::Log(hook) => {
+                for e in &errors {
+                    hook(e);
+                }
+                Ok(())
+            }
+            AdvicePolicy::Strict => Err(errors.into_iter().next().unwrap()),
+        }
+    }
+
+    fn lock_policy(&self) -> MutexGuard<'_, AdvicePolicy> {
+        self.policy.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Record any error as the last error, and return the result.
+    fn record<R>(&self, res: Result<R, MemAdviseError>)
+        -> Result<R, MemAdviseError>
+    {
+        if let Err(ref e) = res {
+            let mut last = self.last_error.lock()
+                .unwrap_or_else(|e| e.into_inner());
+            *last = Some(e.clone());
+        }
+        res
+    }
+
+    fn snapshot(&self) -> AdviceSnapshot {
+        let overflow = *self.lock_overflow();
+        let adv = self.advisors.load(Acquire);
+        let mut advisors = [0u64; 4];
+        let mut saturated = [false; 4];
+        for (i, &level) in LEVELS.iter().enumerate() {
+            advisors[i] = ((adv & level as u64) >> shift(level)) +
+                u64::from(overflow[i]);
+            saturated[i] = is_full(adv, level);
+        }
+        let range_segments = self.lock_ranges().segments.iter().count();
+        let last_error = self.last_error.lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        AdviceSnapshot {
+            handles: 0,
+            advisors,
+            saturated,
+            effective: top_most(adv),
+            pattern: top_pattern(adv),
+            range_segments,
+            last_error,
+        }
+    }
+
+    fn lock_ranges(&self) -> MutexGuard<'_, Ranges> {
+        // Recover from poisoning, as counts are always left consistent
+        self.ranges.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Replace the range advice of a handle, by id, for the page-aligned
+    // region `[start, end)`, and relay changes, collecting any errors.
+    // Returns the highest priority effective advice over the region.
+    fn advise_range(
+        &self,
+        ranges: &mut Ranges,
+        id: u64,
+        start: usize,
+        end: usize,
+        advice: MemAdvice,
+        errors: &mut Vec<MemAdviseError>)
+        -> MemAdvice
+    {
+        let adv = self.advisors.load(Acquire);
+        let mut top = top_most(adv);
+        let Ranges { requests, segments, .. } = ranges;
+        let handle = requests.entry(id).or_insert_with(Segments::new);
+        for (s, e, prior, _) in handle.update(start, end, |_| advice as u64) {
+            let prior = MemAdvice::from(prior);
+            let changes = segments.update(s, e, |c| {
+                c.decr(prior).incr(advice)
+            });
+            for (s, e, old, new) in changes {
+                let (old, new) = (adv | old.packed(), adv | new.packed());
+                let t = self.relay_piece(
+                    s..e, old, new, Some(advice), errors);
+                top = top.max(t);
+            }
+        }
+        top
+    }
+
+    // Release all range advice of a handle, by id, relaying changes.
+    fn release_ranges(&self, id: u64) {
+        let mut errors = Vec::new();
+        {
+            let mut ranges = self.lock_ranges();
+            let Ranges { requests, segments, .. } = &mut *ranges;
+            if let Some(handle) = requests.remove(&id) {
+                let adv = self.advisors.load(Acquire);
+                for &(s, e, prior) in handle.iter() {
+                    let prior = MemAdvice::from(prior);
+                    let changes = segments.update(s, e, |c| c.decr(prior));
+                    for (s, e, old, new) in changes {
+                        let (old, new) =
+                            (adv | old.packed(), adv | new.packed());
+                        self.relay_piece(s..e, old, new, None, &mut errors);
+                    }
+                }
+            }
+        }
+        self.apply_policy(errors).ok();
+    }
+}
+
+// Range advice state, shared by all handles.
+#[derive(Debug, Default)]
+struct Ranges {
+    // Last assigned handle id.
+    last_id: u64,
+    // Range advice (as repr) by handle id.
+    requests: HashMap<u64, Segments<u64>>,
+    // Advisor counts, for range advice only.
+    segments: Segments<Counts>,
+}
+
+// Advisor counts by level, for range advice. As these are only accessed
+// under lock, they are not packed and can't practically saturate.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Counts([u64; 4]);
+
+impl Counts {
+    fn incr(mut self, advice: MemAdvice) -> Counts {
+        if advice != MemAdvice::Normal {
+            self.0[(advice.level() - 1) as usize] += 1;
+        }
+        self
+    }
+
+    fn decr(mut self, prior: MemAdvice) -> Counts {
+        if prior != MemAdvice::Normal {
+            let c = &mut self.0[(prior.level() - 1) as usize];
+            if *c > 0 { *c -= 1; }
+        }
+        self
+    }
+
+    // Return as packed advisors state, with counts saturated. This is
+    // sufficient for determining the top most advice.
+    fn packed(&self) -> u64 {
+        let mut adv = 0;
+        for (i, &level) in LEVELS.iter().enumerate() {
+            adv |= self.0[i].min(COUNT_MAX) << shift(level);
+        }
+        adv
+    }
+}
+
+impl Ranges {
+    // Assign a new, unused handle id (of 32-bits, non-zero).
+    fn next_id(&mut self) -> u64 {
+        loop {
+            self.last_id = (self.last_id + 1) & 0xFFFF_FFFF;
+            if self.last_id != 0 && !self.requests.contains_key(&self.last_id)
+            {
+                return self.last_id;
+            }
+        }
     }
 }
 
-#[derive(Debug)]
-struct Mem<T>
-    where T: Deref<Target=[u8]>
+// Return the page-aligned region of the buffer for range advice: whole pages
+// contained by the range for eviction advice, otherwise the page-aligned
+// superset, clamped to the buffer. An empty range remains empty.
+fn page_range(mem: &[u8], range: Range<usize>, advice: MemAdvice)
+    -> (usize, usize)
 {
-    mem: T,
-    advisors: AtomicU64,
+    if range.start == range.end {
+        return (range.start, range.start);
+    }
+    let base = mem.as_ptr() as usize;
+    let mask = page_size() - 1;
+    let start = base + range.start;
+    let end = base + range.end;
+    let (start, end) = match advice {
+        MemAdvice::DontNeed => {
+            ((start + mask) & !mask, end & !mask)
+        }
+        _ => (start & !mask, (end + mask) & !mask)
+    };
+    let start = start.max(base) - base;
+    let end = (end.max(base) - base).min(mem.len());
+    (start, end.max(start))
 }
 
+#[cfg(all(feature = "linux-advice",
+          any(target_os = "linux", target_os = "android")))]
 impl<T> Mem<T>
     where T: Deref<Target=[u8]>
 {
-    fn new(mem: T) -> Mem<T> {
-        Mem { mem, advisors: AtomicU64::new(0) }
+    // Increment requesters of a flag, relaying it if the first.
+    fn incr_flag(&self, advice: LinuxAdvice) -> Result<(), MemAdviseError> {
+        let mut flags = self.flags.load(Acquire);
+        loop {
+            let new_flags = match linux::incr_flags(flags, advice) {
+                Some(f) => f,
+                None => {
+                    let e = MemAdviseError::saturated()
+                        .at_linux(advice, 0..self.len());
+                    return self.record(Err(e));
+                }
+            };
+            match self.flags.compare_exchange_weak(
+                flags, new_flags, SeqCst, Acquire)
+            {
+                Ok(_) => {
+                    if linux::count(flags, advice) == 0 {
+                        let res = linux::advise(&self.mem, advice, true)
+                            .map_err(|e| e.at_linux(advice, 0..self.len()));
+                        return self.report(res);
+                    }
+                    return Ok(());
+                }
+                Err(x) => flags = x
+            }
+        }
     }
 
-    fn adjust_advice(&self, prior: MemAdvice, advice: MemAdvice)
-        -> Result<MemAdvice, MemAdviseError>
-    {
-        debug_assert!(prior != advice);
-        let mut adv = self.advisors.load(Acquire);
+    // Decrement requesters of a flag, clearing it if the last.
+    fn decr_flag(&self, advice: LinuxAdvice) -> Result<(), MemAdviseError> {
+        let mut flags = self.flags.load(Acquire);
         loop {
-            let old_top = top_most(adv);
-            let new_adv = decr_advisors(adv, prior);
-            let new_adv = incr_advisors(new_adv, advice);
-            let new_top = top_most(new_adv);
-            match self.advisors.compare_exchange_weak(
-                adv, new_adv, SeqCst, Acquire)
+            let new_flags = linux::decr_flags(flags, advice);
+            match self.flags.compare_exchange_weak(
+                flags, new_flags, SeqCst, Acquire)
             {
                 Ok(_) => {
-                    if new_top != old_top {
-                        // Note, may fail after adjustments
-                        advise(&self.mem, new_top)?;
-                        return Ok(new_top);
+                    if linux::count(flags, advice) == 1 {
+                        let res = linux::advise(&self.mem, advice, false)
+                            .map_err(|e| e.at_linux(advice, 0..self.len()));
+                        return self.report(res);
                     }
-                    return Ok(new_top);
+                    return Ok(());
                 }
-                Err(x) => adv = x
+                Err(x) => flags = x
             }
         }
     }
@@ -199,42 +1643,52 @@ impl<T> Deref for Mem<T>
     }
 }
 
+// Bit shift of the advisors count for the given (non-Normal) advice.
+fn shift(advice: MemAdvice) -> u32 {
+    (advice as u64).trailing_zeros()
+}
+
 // Given packed advisors state, and prior advice, return decremented state.
 fn decr_advisors(mut advisors: u64, prior: MemAdvice) -> u64 {
     if prior != MemAdvice::Normal {
         let mut p = advisors & (prior as u64);
         advisors -= p;
-        if prior == MemAdvice::Sequential { p >>= 10; }
+        p >>= shift(prior);
         if p > 0 { p -= 1; }
-        if prior == MemAdvice::Sequential { p <<= 10; }
-        advisors |= p;
+        advisors |= p << shift(prior);
     }
     advisors
 }
 
 // Given packed advisors state, and new advice, return incremented state.
 fn incr_advisors(mut advisors: u64, advice: MemAdvice) -> u64 {
-    let mut cur = advisors & (advice as u64);
-    advisors -= cur;
-    match advice {
-        MemAdvice::Normal => {
-            advisors
-        }
-        MemAdvice::Random => {
-            if cur < 0x3FF { cur += 1; }
-            advisors | cur
-        }
-        MemAdvice::Sequential => {
-            cur >>= 10;
-            if cur < 0x3FF { cur += 1; }
-            cur <<= 10;
-            advisors | cur
-        }
+    if advice != MemAdvice::Normal {
+        let mut cur = advisors & (advice as u64);
+        advisors -= cur;
+        cur >>= shift(advice);
+        if cur < COUNT_MAX { cur += 1; }
+        advisors |= cur << shift(advice);
     }
+    advisors
+}
+
+// Return true if the packed advisors count for the given advice is full.
+fn is_full(advisors: u64, advice: MemAdvice) -> bool {
+    advice != MemAdvice::Normal && (advisors & (advice as u64)) == advice as u64
 }
 
 // Return top most advice from advisors state.
 fn top_most(advisors: u64) -> MemAdvice {
+    for &level in LEVELS.iter().rev() {
+        if (advisors & (level as u64)) > 0 {
+            return level;
+        }
+    }
+    MemAdvice::Normal
+}
+
+// Return top most persistent access pattern from advisors state.
+fn top_pattern(advisors: u64) -> MemAdvice {
     if (advisors & (MemAdvice::Sequential as u64)) > 0 {
         MemAdvice::Sequential
     } else if (advisors & (MemAdvice::Random as u64)) > 0 {
@@ -244,39 +1698,322 @@ fn top_most(advisors: u64) -> MemAdvice {
     }
 }
 
-// Advise the \*nix OS about memory access plans.
+// Advise the \*nix OS about memory access plans. Advice is given for the
+// page-aligned superset of the memory region, or for eviction advice, only
+// the whole pages contained by the region. This is a no-op if the region is
+// empty or (for eviction) contains no whole page.
 #[cfg(unix)]
-fn advise<T>(mem: &T, advice: MemAdvice) -> Result<(), MemAdviseError>
-    where T: Deref<Target=[u8]>
-{
+fn advise(mem: &[u8], advice: MemAdvice) -> Result<(), MemAdviseError> {
+    if mem.is_empty() {
+        return Ok(());
+    }
+    let (addr, len) = match advice {
+        MemAdvice::DontNeed => {
+            page_inner(mem.as_ptr() as usize, mem.len())
+        }
+        _ => page_outer(mem.as_ptr() as usize, mem.len())
+    };
+    if len == 0 {
+        return Ok(());
+    }
+    #[cfg(test)]
+    {
+        if let Some(ecode) = tests::injected_error(advice) {
+            return Err(MemAdviseError::new(ecode));
+        }
+    }
+    let ptr = addr as *mut libc::c_void;
+
+    // POSIX_MADV_DONTNEED is non-destructive, unlike Linux MADV_DONTNEED
     let flags: libc::c_int = match advice {
         MemAdvice::Normal       => libc::POSIX_MADV_NORMAL,
         MemAdvice::Random       => libc::POSIX_MADV_RANDOM,
         MemAdvice::Sequential   => libc::POSIX_MADV_SEQUENTIAL,
+        MemAdvice::WillNeed     => libc::POSIX_MADV_WILLNEED,
+        MemAdvice::DontNeed     => libc::POSIX_MADV_DONTNEED,
     };
 
-    let ptr = &(mem[0]) as *const u8 as *mut libc::c_void;
-    let res = unsafe { libc::posix_madvise(ptr, mem.len(), flags) };
+    let res = unsafe { libc::posix_madvise(ptr, len, flags) };
     if res == 0 {
         Ok(())
     } else {
-        Err(MemAdviseError { ecode: res })
+        Err(MemAdviseError::new(res))
     }
 }
 
 // RAM access advice, currently a no-op for non-\*nix OS
 #[cfg(not(unix))]
-fn advise<T>(_mem: &T, _advice: MemAdvice) -> Result<(), MemAdviseError>
-    where T: Deref<Target=[u8]>
-{
+fn advise(_mem: &[u8], _advice: MemAdvice) -> Result<(), MemAdviseError> {
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
+    #[cfg(unix)] use std::cell::Cell;
     use std::mem;
 
-    use crate::mem::MemHandle;
+    use super::{
+        decr_advisors, incr_advisors, page_range, shift, top_most,
+        top_pattern, COUNT_MAX
+    };
+    use crate::mem::{
+        page_size, AdaptiveReader, AdviceGuard, AlignedBuf, LockGuard,
+        MemAdvice, MemHandle, MemHandleMut, MemReader, Prefetch, WeakMemHandle
+    };
+    use crate::mem::MemAdvice::*;
+
+    #[cfg(unix)]
+    thread_local! {
+        static FAIL_ADVICE: Cell<Option<MemAdvice>> = Cell::new(None);
+    }
+
+    // Fail any subsequent relay of the given advice on this thread, with
+    // EINVAL, or with None, stop failing.
+    #[cfg(unix)]
+    pub(super) fn fail_advice(advice: Option<MemAdvice>) {
+        FAIL_ADVICE.with(|f| f.set(advice));
+    }
+
+    // Return the injected error code, if advice should fail.
+    #[cfg(unix)]
+    pub(super) fn injected_error(advice: MemAdvice) -> Option<i32> {
+        match FAIL_ADVICE.with(|f| f.get()) {
+            Some(a) if a == advice => Some(libc::EINVAL),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_advice_masks() {
+        let mut all = 0;
+        for &a in &[DontNeed, Random, Sequential, WillNeed] {
+            assert_eq!(all & a as u64, 0, "{:?} overlaps", a);
+            assert_eq!((a as u64).count_ones(), 12);
+            assert_eq!(MemAdvice::from_level(a.level()), a);
+            all |= a as u64;
+        }
+    }
+
+    #[test]
+    fn test_advisors_independent() {
+        let levels = [DontNeed, Random, Sequential, WillNeed];
+        let count = |adv: u64, a: MemAdvice| (adv & a as u64) >> shift(a);
+        for (i, &a) in levels.iter().enumerate() {
+            // Start with distinct counts for every level
+            let mut adv = 0;
+            for (j, &b) in levels.iter().enumerate() {
+                for _ in 0..(j + 1) { adv = incr_advisors(adv, b); }
+            }
+            let before = adv;
+            for _ in 0..(COUNT_MAX + 10) { adv = incr_advisors(adv, a); }
+            assert_eq!(count(adv, a), COUNT_MAX);
+            for _ in 0..(COUNT_MAX + 10) { adv = decr_advisors(adv, a); }
+            assert_eq!(count(adv, a), 0);
+            for (j, &b) in levels.iter().enumerate() {
+                if j != i {
+                    assert_eq!(count(adv, b), j as u64 + 1,
+                               "{:?} changed by {:?}", b, a);
+                    assert_eq!(count(adv, b), count(before, b));
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_advise_policy_hook() {
+        use std::sync::{Arc, Mutex};
+
+        use crate::mem::AdvicePolicy;
+
+        let ps = page_size();
+        let h1 = MemHandle::new(AlignedBuf::new(16 * ps));
+        let h2 = h1.clone();
+        h2.advise_range(0..ps, Random).unwrap();
+
+        // The hook may snapshot, and drop a handle with range advice to
+        // the same memory, without deadlock.
+        let weak = h1.downgrade();
+        let held = Mutex::new(Some(h2));
+        let snaps = Arc::new(Mutex::new(Vec::new()));
+        let logged = snaps.clone();
+        h1.set_advice_policy(AdvicePolicy::Log(Arc::new(move |_| {
+            let h = weak.upgrade().unwrap();
+            logged.lock().unwrap().push(h.advice_snapshot());
+            held.lock().unwrap().take();
+        })));
+        fail_advice(Some(Sequential));
+        assert_eq!(h1.advise_range(ps..(2 * ps), Sequential).unwrap(),
+                   Sequential);
+        fail_advice(None);
+
+        let snaps = snaps.lock().unwrap();
+        assert_eq!(snaps.len(), 1);
+        assert_eq!(snaps[0].handles(), 3);
+        assert_eq!(snaps[0].range_segments(), 2);
+        assert!(snaps[0].last_error().is_some());
+        let snap = h1.advice_snapshot();
+        assert_eq!(snap.handles(), 1);
+        assert_eq!(snap.range_segments(), 1);
+    }
+
+    #[test]
+    fn test_advisors_packing() {
+        let mut adv = 0;
+        for &a in &[DontNeed, Random, Sequential, WillNeed] {
+            adv = incr_advisors(adv, a);
+            assert_eq!(top_most(adv), a);
+        }
+        assert_eq!(top_pattern(adv), Sequential);
+        adv = decr_advisors(adv, WillNeed);
+        assert_eq!(top_most(adv), Sequential);
+        adv = decr_advisors(adv, Sequential);
+        adv = decr_advisors(adv, Random);
+        assert_eq!(top_most(adv), DontNeed);
+        assert_eq!(top_pattern(adv), Normal);
+        adv = decr_advisors(adv, DontNeed);
+        assert_eq!(adv, 0);
+    }
+
+    #[test]
+    fn test_advisors_saturate() {
+        let mut adv = 0;
+        for _ in 0..5000 {
+            adv = incr_advisors(adv, DontNeed);
+        }
+        assert_eq!(adv, DontNeed as u64);
+        assert_eq!(top_most(adv), DontNeed);
+        adv = incr_advisors(adv, Random);
+        assert_eq!(top_most(adv), Random);
+        assert_eq!(decr_advisors(adv, WillNeed), adv);
+    }
+
+    #[test]
+    #[cfg(all(feature = "linux-advice",
+              any(target_os = "linux", target_os = "android")))]
+    fn test_linux_saturated() {
+        use crate::mem::LinuxAdvice::DontDump;
+
+        let h0 = MemHandle::new(vec![0u8; 2 * page_size()]);
+        let mut handles = Vec::with_capacity(0xFFFF);
+        for _ in 0..0xFFFF {
+            let h = h0.clone();
+            h.advise_linux(DontDump).unwrap();
+            handles.push(h);
+        }
+        let e = h0.advise_linux(DontDump).unwrap_err();
+        assert!(e.is_saturated());
+        assert_eq!(e.linux_advice(), Some(DontDump));
+        assert_eq!(h0.advice_snapshot().last_error(), Some(&e));
+        // Not requested via h0, so clear is a no-op
+        h0.clear_linux(DontDump).unwrap();
+        handles.pop();
+        h0.advise_linux(DontDump).unwrap();
+        h0.clear_linux(DontDump).unwrap();
+        drop(handles);
+    }
+
+    #[test]
+    fn test_advisors_overflow() {
+        // DontNeed advice for less than a page is never relayed
+        let h0 = MemHandle::new(vec![0u8; 1024]);
+        let mut hs = Vec::new();
+        for _ in 0..4106 {
+            let h = h0.clone();
+            assert_eq!(h.advise(DontNeed).unwrap(), DontNeed);
+            hs.push(h);
+        }
+        let snap = h0.advice_snapshot();
+        assert_eq!(snap.advisors(DontNeed), 4106);
+        assert!(snap.is_saturated(DontNeed));
+        assert!(snap.any_saturated());
+        hs.truncate(4086);
+        let snap = h0.advice_snapshot();
+        assert_eq!(snap.advisors(DontNeed), 4086);
+        assert!(!snap.is_saturated(DontNeed));
+        for _ in 0..9 {
+            let h = h0.clone();
+            h.advise(DontNeed).unwrap();
+            hs.push(h);
+        }
+        assert!(h0.advice_snapshot().is_saturated(DontNeed));
+
+        // Simulate exhausted overflow
+        h0.mem.lock_overflow()[0] = u32::max_value();
+        let h = h0.clone();
+        let e = h.advise(DontNeed).unwrap_err();
+        assert!(e.is_saturated());
+        assert_eq!(e.raw_os_error(), None);
+        assert_eq!(h.advise(Random).unwrap(), Random);
+        drop(h);
+        h0.mem.lock_overflow()[0] = 0;
+        let snap = h0.advice_snapshot();
+        assert_eq!(snap.advisors(DontNeed), 4095);
+        assert_eq!(snap.advisors(Random), 0);
+        assert_eq!(snap.last_error(), Some(&e));
+        hs.clear();
+        assert_eq!(h0.advice_snapshot().advisors(DontNeed), 0);
+    }
+
+    #[test]
+    fn test_page_range() {
+        let ps = page_size();
+        let v = vec![0u8; 4 * ps];
+        let off = ps - (v.as_ptr() as usize % ps); // to first page boundary
+        let len = v.len();
+        assert_eq!(page_range(&v, 0..len, Random), (0, len));
+        assert_eq!(page_range(&v, 5..5, Random), (5, 5));
+        assert_eq!(page_range(&v, 5..5, DontNeed), (5, 5));
+        if off < ps {
+            assert_eq!(page_range(&v, 0..1, Random), (0, off));
+            assert_eq!(page_range(&v, 0..off+1, DontNeed), (off, off));
+            assert_eq!(page_range(&v, 1..off+ps+1, DontNeed),
+                       (off, off+ps));
+            assert_eq!(page_range(&v, off+1..off+2, WillNeed),
+                       (off, off+ps));
+        }
+    }
+
+    #[test]
+    fn test_advise_range_empty() {
+        let ps = page_size();
+        let h = MemHandle::new(vec![0u8; 4 * ps]);
+        assert_eq!(h.advise_range(5..5, Sequential).unwrap(), Normal);
+        assert_eq!(h.advise_range(ps..ps, WillNeed).unwrap(), Normal);
+        let snap = h.advice_snapshot();
+        assert_eq!(snap.range_segments(), 0);
+        assert_eq!(snap.effective(), Normal);
+    }
+
+    #[test]
+    fn test_lock_empty() {
+        let ps = page_size();
+        let h = MemHandle::new(vec![0u8; 4 * ps]);
+        let g = h.lock(5..5).unwrap();
+        assert_eq!(g.len(), 0);
+        assert!(g.is_empty());
+        assert_eq!(h.mem.lock_locks().iter().count(), 0);
+        drop(g);
+        assert_eq!(h.mem.lock_locks().iter().count(), 0);
+    }
+
+    #[test]
+    fn test_advice_snapshot() {
+        let h1 = MemHandle::new(vec![0u8; 1024]);
+        let h2 = h1.clone();
+        let snap = h1.advice_snapshot();
+        assert_eq!(snap.handles(), 2);
+        assert_eq!(snap.effective(), MemAdvice::Normal);
+        assert_eq!(snap.last_error(), None);
+        let _g = h2.advise_scoped(MemAdvice::DontNeed);
+        let snap = h2.advice_snapshot();
+        assert_eq!(snap.advisors(MemAdvice::DontNeed), 1);
+        assert_eq!(snap.effective(), MemAdvice::DontNeed);
+        assert_eq!(snap.pattern(), MemAdvice::Normal);
+        assert!(!snap.any_saturated());
+        // DontNeed is only relayed for whole pages, so no error is expected
+        assert_eq!(snap.last_error(), None);
+    }
 
     #[test]
     fn test_with_any_deref() {
@@ -285,19 +2022,122 @@ mod tests {
         // properly aligned, not memory mapped, etc.
     }
 
-    fn is_send<T: Send>() -> bool { true }
-    fn is_sync<T: Sync>() -> bool { true }
+    #[test]
+    fn test_slice() {
+        let h = MemHandle::new((0u8..100).collect::<Vec<_>>());
+        let s1 = h.slice(10..50);
+        assert_eq!(s1.len(), 40);
+        assert_eq!(s1[0], 10);
+        let (a, b) = s1.split_at(15);
+        assert_eq!(&a[..], &h[10..25]);
+        assert_eq!(&b[..], &h[25..50]);
+        let c = b.slice(5..5).clone();
+        assert!(c.is_empty());
+        drop((h, s1, a, c));
+        let mut b = b.try_into_mut().unwrap();
+        b[0] = 0;
+        let h = b.into_handle();
+        assert_eq!(&h[..3], &[0, 26, 27]);
+    }
+
+    #[test]
+    fn test_slice_advise_empty() {
+        let ps = page_size();
+        let h = MemHandle::new(vec![0u8; 4 * ps]);
+        let s = h.slice(ps+10..ps+10);
+        assert_eq!(s.advise(Random).unwrap(), Normal);
+        let snap = h.advice_snapshot();
+        assert_eq!(snap.range_segments(), 0);
+        assert_eq!(snap.effective(), Normal);
+        assert_eq!(snap.advisors(Random), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_slice_bounds() {
+        let h = MemHandle::new(vec![0u8; 100]);
+        h.slice(10..50).slice(30..41);
+    }
+
+    #[test]
+    #[cfg(feature = "bytes")]
+    fn test_into_bytes() {
+        let h = MemHandle::new((0u8..100).collect::<Vec<_>>());
+        let ptr = h[10..].as_ptr();
+        let b = bytes::Bytes::from(h.slice(10..50));
+        assert_eq!(b.as_ptr(), ptr); // zero-copy
+        assert_eq!(b.len(), 40);
+        let b2 = b.slice(5..10);
+        drop(b);
+        assert_eq!(h.advice_snapshot().handles(), 2);
+        drop(h);
+        assert_eq!(&b2[..], &[15, 16, 17, 18, 19]);
+    }
+
+    #[test]
+    fn test_prefetch() {
+        let h = MemHandle::new(vec![1u8; 16 * page_size()]);
+        let p = h.prefetch(page_size()..16 * page_size());
+        drop(h);
+        assert!(p.wait());
+        assert_eq!(p.total(), 15 * page_size());
+    }
+
+    #[test]
+    fn test_downgrade() {
+        let h = MemHandle::new(vec![1u8; 1024]);
+        assert_eq!(h.advise(Random).unwrap(), Random);
+        let w = h.slice(10..20).downgrade();
+        assert_eq!(w.len(), 10);
+        assert_eq!(h.advice_snapshot().handles(), 1);
+
+        let u = w.clone().upgrade().unwrap();
+        assert_eq!(u.len(), 10);
+        assert_eq!(u[0], 1);
+        assert_eq!(u.advise(Normal).unwrap(), Random);
+        let snap = h.advice_snapshot();
+        assert_eq!(snap.handles(), 2);
+        assert_eq!(snap.advisors(Random), 1);
+        drop(u);
+
+        drop(h);
+        assert!(w.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_handle_mut() {
+        let mut m = MemHandleMut::new(vec![0u8; 1024]);
+        m[1] = 1;
+        let h = m.into_handle();
+        assert_eq!(h[1], 1);
+        let h2 = h.clone();
+        let h = h.try_into_mut().unwrap_err();
+        drop(h2);
+        let mut m = h.try_into_mut().unwrap();
+        m[2] = 2;
+        assert_eq!(&m[..3], &[0, 1, 2]);
+    }
+
+    // Compile time assertions, shared by the other mem types
+    fn assert_send_sync<T: Send + Sync>() {}
 
     #[test]
     fn test_send_sync() {
-        assert!(is_send::<MemHandle<Vec<u8>>>());
-        assert!(is_sync::<MemHandle<Vec<u8>>>());
+        assert_send_sync::<MemHandle<Vec<u8>>>();
+        assert_send_sync::<MemHandleMut<Vec<u8>>>();
+        assert_send_sync::<LockGuard<'static, Vec<u8>>>();
+        assert_send_sync::<AdviceGuard<'static, Vec<u8>>>();
+        assert_send_sync::<WeakMemHandle<Vec<u8>>>();
+        assert_send_sync::<Prefetch>();
+        assert_send_sync::<AlignedBuf>();
+        assert_send_sync::<AdaptiveReader<Vec<u8>>>();
+        assert_send_sync::<MemReader<Vec<u8>>>();
     }
 
     #[test]
     #[cfg(target_pointer_width = "64")]
     fn test_size() {
-        assert_eq!(mem::size_of::<MemHandle<Vec<u8>>>(), 16);
+        assert_eq!(mem::size_of::<MemHandle<Vec<u8>>>(), 32);
     }
 
     #[cfg(feature = "mmap")]
@@ -310,9 +2150,21 @@ mod tests {
 
         use rand::seq::SliceRandom;
 
-        use crate::mem::MemHandle;
+        use super::assert_send_sync;
+        #[cfg(unix)] use super::fail_advice;
+        use super::super::top_most;
+        use crate::mem::{
+            page_size, GrowableMap, MappedSlice, MappedSliceMut, MemHandle
+        };
         use crate::mem::MemAdvice::*;
 
+        #[test]
+        fn test_send_sync() {
+            assert_send_sync::<MappedSlice>();
+            assert_send_sync::<MappedSliceMut>();
+            assert_send_sync::<GrowableMap>();
+        }
+
         #[test]
         fn test_advise_one() {
             let map = {
@@ -380,10 +2232,356 @@ mod tests {
             assert_eq!(h3.advise(Normal).unwrap(),     Random); //h2 remains
         }
 
+        #[test]
+        fn test_advise_scoped() {
+            let map = {
+                let mut f = tempfile().unwrap();
+                f.write_all(&vec![1u8; 256 * 1024]).unwrap();
+                unsafe { Mmap::map(&f) }.unwrap()
+            };
+            let h1 = MemHandle::new(map);
+            let h2 = h1.clone();
+            assert_eq!(h1.advise(Random).unwrap(), Random);
+            {
+                let g1 = h1.advise_scoped(Sequential).unwrap();
+                assert_eq!(g1.effective(), Sequential);
+                assert_eq!(g1.prior(), Random);
+                assert_eq!(1u8, g1[0]);
+                {
+                    let g2 = h2.advise_scoped(WillNeed).unwrap();
+                    assert_eq!(g2.effective(), WillNeed);
+                    let g3 = h1.advise_scoped(Random).unwrap();
+                    assert_eq!(g3.effective(), WillNeed);
+                    assert_eq!(g3.prior(), Sequential);
+                }
+                assert_eq!(h2.advise(DontNeed).unwrap(), Sequential);
+            }
+            assert_eq!(h2.advise(DontNeed).unwrap(), Random);
+            drop(h1);
+            assert_eq!(h2.advise(Normal).unwrap(), Normal);
+        }
+
+        #[test]
+        fn test_advise_range() {
+            let ps = page_size();
+            let map = {
+                let mut f = tempfile().unwrap();
+                f.write_all(&vec![1u8; 16 * ps]).unwrap();
+                unsafe { Mmap::map(&f) }.unwrap()
+            };
+            let h1 = MemHandle::new(map);
+            let h2 = h1.clone();
+            assert_eq!(h1.advise_range(0..4*ps, Sequential).unwrap(),
+                       Sequential);
+            assert_eq!(h2.advise_range(2*ps..6*ps-1, Random).unwrap(),
+                       Sequential);
+            assert_eq!(h2.advise_range(8*ps..9*ps, WillNeed).unwrap(),
+                       WillNeed);
+            assert_eq!(h2.advise_range(8*ps..9*ps, WillNeed).unwrap(),
+                       WillNeed); // repeat
+            assert_eq!(h1.advise(Random).unwrap(), Random);
+            assert_eq!(h1.advise_range(10*ps..16*ps, DontNeed).unwrap(),
+                       Random);
+            assert_eq!(1u8, h1[12*ps]);
+            {
+                let ranges = h1.mem.lock_ranges();
+                let levels = ranges.segments.iter()
+                    .map(|&(s, e, c)| (s / ps, e / ps, top_most(c.packed())))
+                    .collect::<Vec<_>>();
+                assert_eq!(levels, vec![
+                    (0, 2, Sequential),
+                    (2, 4, Sequential),
+                    (4, 6, Random),
+                    (8, 9, WillNeed),
+                    (10, 16, DontNeed),
+                ]);
+            }
+            // Replaces prior range advice of h1
+            assert_eq!(h1.advise_range(0..3*ps, Normal).unwrap(), Random);
+            drop(h1);
+            {
+                let ranges = h2.mem.lock_ranges();
+                let levels = ranges.segments.iter()
+                    .map(|&(s, e, c)| (s / ps, e / ps, top_most(c.packed())))
+                    .collect::<Vec<_>>();
+                assert_eq!(levels, vec![
+                    (2, 6, Random),
+                    (8, 9, WillNeed),
+                ]);
+            }
+            assert_eq!(h2.advise_range(0..16*ps, Normal).unwrap(), Normal);
+            assert_eq!(h2.mem.lock_ranges().segments.iter().count(), 0);
+        }
+
+        #[test]
+        fn test_advise_slices() {
+            let ps = page_size();
+            let map = {
+                let mut f = tempfile().unwrap();
+                f.write_all(&vec![1u8; 16 * ps]).unwrap();
+                unsafe { Mmap::map(&f) }.unwrap()
+            };
+            let h = MemHandle::new(map);
+            let (a, b) = h.split_at(8 * ps);
+            let c = b.slice(4*ps..8*ps);
+            assert_eq!(a.advise(Sequential).unwrap(), Sequential);
+            assert_eq!(b.advise(Random).unwrap(), Random);
+            assert_eq!(c.advise(WillNeed).unwrap(), WillNeed);
+            assert_eq!(c.advise(WillNeed).unwrap(), WillNeed); // repeat
+            assert_eq!(b.advise_range(0..ps, Sequential).unwrap(),
+                       Sequential);
+            assert_eq!(1u8, c[0]);
+            {
+                let _g = c.advise_scoped(Normal).unwrap();
+                let levels = h.mem.lock_ranges().segments.iter()
+                    .map(|&(s, e, c)| (s / ps, e / ps, top_most(c.packed())))
+                    .collect::<Vec<_>>();
+                // b's range advice replaced its Random for the first page
+                assert_eq!(levels, vec![(0, 9, Sequential), (9, 16, Random)]);
+            }
+            drop(a);
+            drop(b);
+            let levels = h.mem.lock_ranges().segments.iter()
+                .map(|&(s, e, c)| (s / ps, e / ps, top_most(c.packed())))
+                .collect::<Vec<_>>();
+            assert_eq!(levels, vec![(12, 16, WillNeed)]);
+            // Whole handle advice is independent
+            assert_eq!(h.advise(Random).unwrap(), Random);
+            drop(c);
+            assert_eq!(h.mem.lock_ranges().segments.iter().count(), 0);
+        }
+
+        #[test]
+        fn test_lock() {
+            let ps = page_size();
+            let map = {
+                let mut f = tempfile().unwrap();
+                f.write_all(&vec![1u8; 4 * ps]).unwrap();
+                unsafe { Mmap::map(&f) }.unwrap()
+            };
+            let h1 = MemHandle::new(map);
+            let h2 = h1.slice(ps..4*ps);
+            let g1 = match h1.lock(1..2*ps) {
+                Ok(g) => g,
+                Err(e) => {
+                    // May fail, depending on RLIMIT_MEMLOCK
+                    assert!(e.is_limit_exceeded(), "{}", e);
+                    return;
+                }
+            };
+            assert_eq!(g1.len(), 2 * ps);
+            let g2 = h2.lock(0..2*ps).unwrap();
+            let counts = |h: &MemHandle<Mmap>| {
+                h.mem.lock_locks().iter()
+                    .map(|&(s, e, c)| (s / ps, e / ps, c))
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(counts(&h1), vec![(0, 1, 1), (1, 2, 2), (2, 3, 1)]);
+            drop(g1);
+            assert_eq!(counts(&h1), vec![(1, 3, 1)]);
+            let g3 = h1.lock(0..0).unwrap();
+            assert!(g3.is_empty());
+            // Guards are not counted as handles
+            assert_eq!(h1.advice_snapshot().handles(), 2);
+            drop(g2);
+            drop(h2);
+            assert_eq!(counts(&h1), vec![]);
+            drop(g3);
+            drop(h1);
+        }
+
+        #[test]
+        fn test_lock_map_options() {
+            use crate::fs::ReadSlice;
+            use crate::mem::MapOptions;
+
+            let ps = page_size();
+            let mut f = tempfile().unwrap();
+            f.write_all(&vec![1u8; 4 * ps]).unwrap();
+            let opts = MapOptions::new().lock();
+            let rs = ReadSlice::new(&f, 0, 4 * ps as u64);
+            let h = match rs.mem_map_with(&opts) {
+                Ok(h) => h,
+                // May fail, depending on RLIMIT_MEMLOCK, or if unsupported
+                Err(_) => return,
+            };
+            let counts = |h: &MemHandle<_>| {
+                h.mem.lock_locks().iter()
+                    .map(|&(s, e, c)| (s / ps, e / ps, c))
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(counts(&h), vec![(0, 4, 1)]);
+            let g = h.lock(ps..2*ps).unwrap();
+            assert_eq!(counts(&h), vec![(0, 1, 1), (1, 2, 2), (2, 4, 1)]);
+            drop(g);
+            // Remains locked, as requested via MapOptions
+            assert_eq!(counts(&h), vec![(0, 4, 1)]);
+        }
+
+        #[test]
+        #[should_panic]
+        fn test_advise_range_bounds() {
+            let h = MemHandle::new(vec![0u8; 1024]);
+            h.advise_range(512..1025, Random).ok();
+        }
+
+        #[test]
+        fn test_advise_one_shot() {
+            let map = {
+                let mut f = tempfile().unwrap();
+                f.write_all(&vec![1u8; 256 * 1024]).unwrap();
+                unsafe { Mmap::map(&f) }.unwrap()
+            };
+            let h1 = MemHandle::new(map);
+            let h2 = h1.clone();
+            assert_eq!(h1.advise(WillNeed).unwrap(),   WillNeed);
+            assert_eq!(h1.advise(WillNeed).unwrap(),   WillNeed); // repeat
+            assert_eq!(h2.advise(Sequential).unwrap(), WillNeed);
+            assert_eq!(h1.advise(DontNeed).unwrap(),   Sequential);
+            assert_eq!(1u8, h1[0]);
+            drop(h2);
+            assert_eq!(h1.advise(DontNeed).unwrap(),   DontNeed); // repeat
+            // Shared file mapping is re-read on access
+            assert_eq!(1u8, h1[128*1024]);
+            assert_eq!(h1.advise(Normal).unwrap(),     Normal);
+        }
+
+        #[test]
+        fn test_advise_eviction_preserves() {
+            use memmap::MmapMut;
+
+            let ps = page_size();
+            let mut map = MmapMut::map_anon(16 * ps).unwrap();
+            map[0] = 1;
+            map[15 * ps] = 2;
+            let h1 = MemHandle::new(map);
+            let h2 = h1.clone();
+            assert_eq!(h2.advise(DontNeed).unwrap(), DontNeed);
+            assert_eq!(h2.advise(DontNeed).unwrap(), DontNeed); // repeat
+            assert_eq!(h2.advise_range(0..(16 * ps), DontNeed).unwrap(),
+                       DontNeed);
+            // Content seen via other handles is never lost
+            assert_eq!(h1[0], 1);
+            assert_eq!(h1[15 * ps], 2);
+
+            let h = MemHandle::new(vec![3u8; 16 * ps]);
+            assert_eq!(h.clone().advise(DontNeed).unwrap(), DontNeed);
+            assert_eq!(h.advise(DontNeed).unwrap(), DontNeed);
+            assert!(h.iter().all(|&b| b == 3));
+        }
+
+        #[test]
+        #[cfg(all(feature = "linux-advice",
+                  any(target_os = "linux", target_os = "android")))]
+        fn test_advise_linux() {
+            use crate::mem::LinuxAdvice::*;
+
+            let map = {
+                let mut f = tempfile().unwrap();
+                f.write_all(&vec![1u8; 256 * 1024]).unwrap();
+                unsafe { Mmap::map(&f) }.unwrap()
+            };
+            let h1 = MemHandle::new(map);
+            let h2 = h1.clone();
+            h1.advise_linux(DontDump).unwrap();
+            h1.advise_linux(DontDump).unwrap(); // no-op
+            h2.advise_linux(DontDump).unwrap();
+            assert_eq!(h1.advise(Random).unwrap(), Random);
+            h1.clear_linux(DontDump).unwrap();
+            h1.clear_linux(DontDump).unwrap(); // no-op
+            assert_eq!(h1.advise(Normal).unwrap(), Normal);
+            drop(h2); // last requester, cleared
+            h1.advise_linux(HugePage).unwrap();
+            for &a in &[Cold, PageOut] {
+                // MADV_COLD and MADV_PAGEOUT require Linux 5.4+
+                if let Err(e) = h1.advise_linux(a) {
+                    assert!(e.to_string().ends_with(
+                        &libc::EINVAL.to_string()));
+                }
+            }
+            assert_eq!(1u8, h1[0]);
+        }
+
+        #[test]
+        #[cfg(unix)]
+        fn test_advise_last_error() {
+            let map = {
+                let mut f = tempfile().unwrap();
+                f.write_all(&vec![1u8; 64 * 1024]).unwrap();
+                unsafe { Mmap::map(&f) }.unwrap()
+            };
+            let h = MemHandle::new(map);
+            fail_advice(Some(DontNeed));
+            let e = h.advise(DontNeed).unwrap_err();
+            fail_advice(None);
+            let snap = h.advice_snapshot();
+            assert_eq!(snap.last_error(), Some(&e));
+            assert_eq!(snap.advisors(DontNeed), 1);
+        }
+
+        #[test]
+        #[cfg(unix)]
+        fn test_advise_policy() {
+            use std::io;
+            use std::sync::{Arc, Mutex};
+
+            use crate::mem::AdvicePolicy;
+
+            let map = {
+                let mut f = tempfile().unwrap();
+                f.write_all(&vec![1u8; 64 * 1024]).unwrap();
+                unsafe { Mmap::map(&f) }.unwrap()
+            };
+            let h1 = MemHandle::new(map);
+            let h2 = h1.slice(page_size()..(2 * page_size()));
+
+            fail_advice(Some(DontNeed));
+            let e = h2.advise(DontNeed).unwrap_err();
+            assert_eq!(e.advice(), Some(DontNeed));
+            assert_eq!(e.region(), page_size()..(2 * page_size()));
+            assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+            assert_eq!(io::Error::from(e).kind(), io::ErrorKind::InvalidInput);
+            h2.advise(Normal).unwrap();
+
+            h1.set_advice_policy(AdvicePolicy::Ignore);
+            assert_eq!(h2.advise(DontNeed).unwrap(), DontNeed);
+            assert!(h1.advice_snapshot().last_error().is_some());
+            h2.advise(Normal).unwrap();
+
+            let logged = Arc::new(Mutex::new(Vec::new()));
+            let log = logged.clone();
+            h2.set_advice_policy(AdvicePolicy::Log(Arc::new(move |e| {
+                log.lock().unwrap().push(e.clone());
+            })));
+            match h1.advice_policy() {
+                AdvicePolicy::Log(_) => {}
+                p => panic!("unexpected policy {:?}", p),
+            }
+            let h3 = h1.clone();
+            assert_eq!(h3.advise(DontNeed).unwrap(), DontNeed);
+            {
+                let logged = logged.lock().unwrap();
+                assert_eq!(logged.len(), 1);
+                assert_eq!(logged[0].region(), 0..(64 * 1024));
+            }
+            // Eviction advice is not restored on drop, so force a failure
+            // with DontNeed range advice on release of a Random range.
+            h3.advise(Normal).unwrap();
+            h3.advise_range(0..page_size(), Random).unwrap();
+            h1.advise_range(0..page_size(), DontNeed).unwrap();
+            logged.lock().unwrap().clear();
+            drop(h3);
+            let logged = logged.lock().unwrap();
+            assert_eq!(logged.len(), 1);
+            assert_eq!(logged[0].advice(), Some(DontNeed));
+            assert_eq!(logged[0].region(), 0..page_size());
+            fail_advice(None);
+        }
+
         #[test]
         fn test_advise_threaded() {
             let mut rng = rand::thread_rng();
-            let one_of = vec![Normal, Random, Sequential];
+            let one_of = vec![Normal, DontNeed, Random, Sequential, WillNeed];
             let map = {
                 let mut f = tempfile().unwrap();
                 f.write_all(&vec![2u8; 64 * 1024]).unwrap();
@@ -0,0 +1,151 @@
+use std::io;
+
+use crate::mem::handle::MemAdviseError;
+use crate::mem::page::{page_inner, page_outer};
+
+// Not (yet) defined by all supported libc versions, Linux 5.4+.
+const MADV_COLD: i32 = 20;
+const MADV_PAGEOUT: i32 = 21;
+
+/// Linux-specific memory advice flags, applied via `libc::madvise`.
+///
+/// Unlike [`MemAdvice`](crate::mem::MemAdvice), these are orthogonal to each
+/// other and to the access pattern advice, so are not prioritized. See
+/// [`MemHandle::advise_linux`](crate::mem::MemHandle::advise_linux).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LinuxAdvice {
+    /// Enable transparent huge pages for the region (`MADV_HUGEPAGE`). When
+    /// cleared, `MADV_NOHUGEPAGE` is relayed, which excludes the region even
+    /// if the system-wide setting is "always".
+    HugePage,
+    /// Exclude the region from core dumps (`MADV_DONTDUMP`). When cleared,
+    /// `MADV_DODUMP` is relayed.
+    DontDump,
+    /// Deactivate the region's pages, making them a preferred target for
+    /// reclaim under memory pressure (`MADV_COLD`, Linux 5.4+). One-shot.
+    Cold,
+    /// Reclaim the region's pages now (`MADV_PAGEOUT`, Linux 5.4+). One-shot.
+    PageOut,
+}
+
+impl LinuxAdvice {
+    // Return the handle state bit for flag (persistent) advice, or None for
+    // one-shot advice.
+    pub(crate) fn flag_bit(self) -> Option<u64> {
+        match self {
+            LinuxAdvice::HugePage => Some(1 << 8),
+            LinuxAdvice::DontDump => Some(1 << 9),
+            LinuxAdvice::Cold     |
+            LinuxAdvice::PageOut  => None,
+        }
+    }
+}
+
+// Flag advice, for release of handle state bits on drop.
+pub(crate) const FLAGS: [LinuxAdvice; 2] = [
+    LinuxAdvice::HugePage,
+    LinuxAdvice::DontDump,
+];
+
+// Bit shift of the shared requester count for the given flag advice. Each
+// flag is allocated 16-bits.
+fn shift(advice: LinuxAdvice) -> u32 {
+    match advice {
+        LinuxAdvice::HugePage => 0,
+        _                     => 16,
+    }
+}
+
+// Given packed flag requester counts, return the count for advice.
+pub(crate) fn count(flags: u64, advice: LinuxAdvice) -> u64 {
+    (flags >> shift(advice)) & 0xFFFF
+}
+
+// Given packed flag requester counts, return incremented counts, or None if
+// the count for advice is saturated.
+pub(crate) fn incr_flags(flags: u64, advice: LinuxAdvice) -> Option<u64> {
+    if count(flags, advice) < 0xFFFF {
+        Some(flags + (1 << shift(advice)))
+    } else {
+        None
+    }
+}
+
+// Given packed flag requester counts, return decremented counts.
+pub(crate) fn decr_flags(flags: u64, advice: LinuxAdvice) -> u64 {
+    if count(flags, advice) > 0 {
+        flags - (1 << shift(advice))
+    } else {
+        flags
+    }
+}
+
+// Relay Linux-specific advice for the region, setting or clearing a flag.
+// Flags are applied to the page-aligned superset of the region, and
+// reclaim advice only to the whole pages it contains.
+pub(crate) fn advise(mem: &[u8], advice: LinuxAdvice, set: bool)
+    -> Result<(), MemAdviseError>
+{
+    if mem.is_empty() {
+        return Ok(());
+    }
+    let (addr, len) = match advice {
+        LinuxAdvice::Cold | LinuxAdvice::PageOut => {
+            page_inner(mem.as_ptr() as usize, mem.len())
+        }
+        _ => page_outer(mem.as_ptr() as usize, mem.len())
+    };
+    if len == 0 {
+        return Ok(());
+    }
+    let flags = match (advice, set) {
+        (LinuxAdvice::HugePage, true)  => libc::MADV_HUGEPAGE,
+        (LinuxAdvice::HugePage, false) => libc::MADV_NOHUGEPAGE,
+        (LinuxAdvice::DontDump, true)  => libc::MADV_DONTDUMP,
+        (LinuxAdvice::DontDump, false) => libc::MADV_DODUMP,
+        (LinuxAdvice::Cold, _)         => MADV_COLD,
+        (LinuxAdvice::PageOut, _)      => MADV_PAGEOUT,
+    };
+    let res = unsafe {
+        libc::madvise(addr as *mut libc::c_void, len, flags)
+    };
+    if res == 0 {
+        Ok(())
+    } else {
+        let ecode = io::Error::last_os_error().raw_os_error().unwrap_or(0);
+        Err(MemAdviseError::new(ecode))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{count, decr_flags, incr_flags};
+    use super::LinuxAdvice::*;
+
+    #[test]
+    fn test_flags_packing() {
+        let mut flags = incr_flags(0, HugePage).unwrap();
+        flags = incr_flags(flags, DontDump).unwrap();
+        flags = incr_flags(flags, DontDump).unwrap();
+        assert_eq!(count(flags, HugePage), 1);
+        assert_eq!(count(flags, DontDump), 2);
+        flags = decr_flags(flags, HugePage);
+        flags = decr_flags(flags, HugePage);
+        assert_eq!(count(flags, HugePage), 0);
+        assert_eq!(count(flags, DontDump), 2);
+    }
+
+    #[test]
+    fn test_flags_saturate() {
+        let mut flags = 0;
+        for _ in 0..0xFFFF {
+            flags = incr_flags(flags, HugePage).unwrap();
+        }
+        assert_eq!(count(flags, HugePage), 0xFFFF);
+        assert_eq!(incr_flags(flags, HugePage), None);
+        flags = incr_flags(flags, DontDump).unwrap();
+        assert_eq!(count(flags, DontDump), 1);
+        flags = decr_flags(flags, HugePage);
+        assert_eq!(count(flags, HugePage), 0xFFFE);
+    }
+}
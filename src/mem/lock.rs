@@ -0,0 +1,178 @@
+use std::fmt;
+use std::io;
+
+#[cfg(unix)]
+use crate::mem::page::page_outer;
+
+/// Error from locking memory in RAM, via `libc::mlock` or other platform
+/// equivalent.
+///
+/// Failure due to the `RLIMIT_MEMLOCK` resource limit is distinguished, see
+/// [`MemLockError::is_limit_exceeded`]. Implements `std::error::Error` and
+/// may be converted to an `io::Error`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MemLockError {
+    kind: Kind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Kind {
+    Os(i32),
+    LimitExceeded { ecode: i32, limit: u64 },
+    Unsupported,
+}
+
+impl MemLockError {
+    /// Return true if locking failed because it would exceed the process
+    /// `RLIMIT_MEMLOCK` resource limit.
+    pub fn is_limit_exceeded(&self) -> bool {
+        match self.kind {
+            Kind::LimitExceeded { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Return the `RLIMIT_MEMLOCK` limit in bytes, if exceeded.
+    pub fn limit(&self) -> Option<u64> {
+        match self.kind {
+            Kind::LimitExceeded { limit, .. } => Some(limit),
+            _ => None,
+        }
+    }
+
+    /// Return true if memory locking is not supported on this platform.
+    pub fn is_unsupported(&self) -> bool {
+        self.kind == Kind::Unsupported
+    }
+
+    /// Return the operating system error code, if applicable.
+    pub fn raw_os_error(&self) -> Option<i32> {
+        match self.kind {
+            Kind::Os(ecode) => Some(ecode),
+            Kind::LimitExceeded { ecode, .. } => Some(ecode),
+            Kind::Unsupported => None,
+        }
+    }
+}
+
+impl fmt::Display for MemLockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            Kind::Os(ecode) => {
+                write!(f, "libc::mlock error return code {}", ecode)
+            }
+            Kind::LimitExceeded { ecode, limit } => {
+                write!(f, "libc::mlock exceeds RLIMIT_MEMLOCK of {} bytes \
+                           (error return code {})", limit, ecode)
+            }
+            Kind::Unsupported => {
+                write!(f, "memory locking is not supported on this platform")
+            }
+        }
+    }
+}
+
+impl std::error::Error for MemLockError {}
+
+impl From<MemLockError> for io::Error {
+    fn from(me: MemLockError) -> io::Error {
+        io::Error::new(io::ErrorKind::Other, me)
+    }
+}
+
+// Classify an mlock error code. With Linux, `ENOMEM` results when the limit
+// would be exceeded (for an unprivileged process), and `EPERM` when the
+// limit is 0. Elsewhere, `EAGAIN` may also result.
+#[cfg(unix)]
+#[allow(clippy::unnecessary_cast)] // rlim_t is not u64 on all platforms
+fn lock_error(ecode: i32) -> MemLockError {
+    if ecode == libc::ENOMEM || ecode == libc::EPERM || ecode == libc::EAGAIN {
+        let mut rl = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+        let res = unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut rl) };
+        if res == 0 && rl.rlim_cur != libc::RLIM_INFINITY {
+            return MemLockError {
+                kind: Kind::LimitExceeded {
+                    ecode,
+                    limit: rl.rlim_cur as u64
+                }
+            };
+        }
+    }
+    MemLockError { kind: Kind::Os(ecode) }
+}
+
+// Lock the page-aligned superset of the memory region in RAM.
+#[cfg(unix)]
+pub(crate) fn mlock(mem: &[u8]) -> Result<(), MemLockError> {
+    if mem.is_empty() {
+        return Ok(());
+    }
+    let (addr, len) = page_outer(mem.as_ptr() as usize, mem.len());
+    let res = unsafe { libc::mlock(addr as *const libc::c_void, len) };
+    if res == 0 {
+        Ok(())
+    } else {
+        let ecode = io::Error::last_os_error().raw_os_error().unwrap_or(0);
+        Err(lock_error(ecode))
+    }
+}
+
+// Unlock the page-aligned superset of the memory region.
+#[cfg(unix)]
+pub(crate) fn munlock(mem: &[u8]) -> Result<(), MemLockError> {
+    if mem.is_empty() {
+        return Ok(());
+    }
+    let (addr, len) = page_outer(mem.as_ptr() as usize, mem.len());
+    let res = unsafe { libc::munlock(addr as *const libc::c_void, len) };
+    if res == 0 {
+        Ok(())
+    } else {
+        let ecode = io::Error::last_os_error().raw_os_error().unwrap_or(0);
+        Err(MemLockError { kind: Kind::Os(ecode) })
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn mlock(_mem: &[u8]) -> Result<(), MemLockError> {
+    Err(MemLockError { kind: Kind::Unsupported })
+}
+
+#[cfg(not(unix))]
+pub(crate) fn munlock(_mem: &[u8]) -> Result<(), MemLockError> {
+    Err(MemLockError { kind: Kind::Unsupported })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    #[allow(clippy::unnecessary_cast)]
+    fn test_lock_error() {
+        let mut rl = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+        assert_eq!(0, unsafe {
+            libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut rl)
+        });
+        let e = lock_error(libc::ENOMEM);
+        assert_eq!(e.raw_os_error(), Some(libc::ENOMEM));
+        if rl.rlim_cur == libc::RLIM_INFINITY {
+            assert!(!e.is_limit_exceeded());
+        } else {
+            assert!(e.is_limit_exceeded());
+            assert_eq!(e.limit(), Some(rl.rlim_cur as u64));
+            assert!(e.to_string().contains("RLIMIT_MEMLOCK"));
+        }
+        let e = lock_error(libc::EINVAL);
+        assert!(!e.is_limit_exceeded());
+        assert!(!e.is_unsupported());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_lock_empty() {
+        mlock(&[]).unwrap();
+        munlock(&[]).unwrap();
+    }
+}
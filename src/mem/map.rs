@@ -0,0 +1,428 @@
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::ops::{Deref, DerefMut};
+
+use memmap::{Mmap, MmapMut, MmapOptions};
+
+use crate::mem::{page_size, MemHandle, MemHandleMut};
+use crate::mem::lock::mlock;
+use crate::mem::page::touch;
+#[cfg(unix)] use crate::mem::page::page_outer;
+
+/// Options for memory mapping, used with
+/// [`crate::fs::ReadSlice::mem_map_with`] and
+/// [`crate::fs::ReadSlice::mem_map_copy`].
+///
+/// These options are applied to the page-aligned superset of the mapped
+/// range, after mapping. Options not supported on the current platform are
+/// either ignored (if only a hint) or result in an error.
+///
+/// This requires the _mmap_ feature.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MapOptions {
+    populate: bool,
+    lock: bool,
+    huge_pages: bool,
+}
+
+impl MapOptions {
+    /// New options, for a plain mapping.
+    pub fn new() -> MapOptions {
+        MapOptions::default()
+    }
+
+    /// Prefault (populate) all pages of the mapping, as with `MAP_POPULATE`,
+    /// by reading a byte of each page before returning. Subsequent access
+    /// then incurs no page faults, as long as the pages remain resident.
+    pub fn populate(mut self) -> MapOptions {
+        self.populate = true;
+        self
+    }
+
+    /// Lock all pages of the mapping in RAM via `mlock`, preventing these
+    /// from being paged out. The lock is released when the mapping is
+    /// dropped, and not by any `MemHandle::lock` guard. This is subject to
+    /// `RLIMIT_MEMLOCK`, and results in an error if exceeded, or on non-unix
+    /// platforms.
+    pub fn lock(mut self) -> MapOptions {
+        self.lock = true;
+        self
+    }
+
+    /// Hint that transparent huge pages should be used, via `madvise` with
+    /// `MADV_HUGEPAGE` on Linux. Results in an error if the kernel lacks
+    /// transparent huge page support. This is ignored on other platforms.
+    pub fn huge_pages(mut self) -> MapOptions {
+        self.huge_pages = true;
+        self
+    }
+
+    // Apply options to the mapped (non-empty) memory region.
+    pub(crate) fn apply(&self, mem: &[u8]) -> io::Result<()> {
+        if mem.is_empty() {
+            return Ok(());
+        }
+        if self.huge_pages {
+            huge_pages(mem)?;
+        }
+        if self.lock {
+            // Retain the OS error code, for compatibility
+            mlock(mem).map_err(|e| match e.raw_os_error() {
+                Some(ecode) => io::Error::from_raw_os_error(ecode),
+                None => e.into(),
+            })?;
+        }
+        if self.populate {
+            touch(mem);
+        }
+        Ok(())
+    }
+
+    // Apply options to the mapped memory, returning a new handle to it. Any
+    // lock applied is held for the life of the memory.
+    pub(crate) fn handle<T>(&self, mem: T) -> io::Result<MemHandle<T>>
+        where T: Deref<Target=[u8]>
+    {
+        self.apply(&mem)?;
+        let handle = MemHandle::new(mem);
+        if self.lock {
+            handle.hold_lock();
+        }
+        Ok(handle)
+    }
+
+    // Apply options to the mapped memory, returning a new writable handle to
+    // it. Any lock applied is held for the life of the memory.
+    pub(crate) fn handle_mut<T>(&self, mem: T) -> io::Result<MemHandleMut<T>>
+        where T: DerefMut<Target=[u8]>
+    {
+        self.apply(&mem)?;
+        let handle = MemHandleMut::new(mem);
+        if self.lock {
+            handle.hold_lock();
+        }
+        Ok(handle)
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn huge_pages(mem: &[u8]) -> io::Result<()> {
+    let (addr, len) = page_outer(mem.as_ptr() as usize, mem.len());
+    let res = unsafe {
+        libc::madvise(addr as *mut libc::c_void, len, libc::MADV_HUGEPAGE)
+    };
+    if res == 0 { Ok(()) } else { Err(io::Error::last_os_error()) }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn huge_pages(_mem: &[u8]) -> io::Result<()> {
+    Ok(())
+}
+
+/// A read-only memory mapped region of a file, exposing exactly the
+/// requested byte range.
+///
+/// The mapping itself covers the page-aligned superset of the requested
+/// range, as required by the operating system, but only the requested range
+/// is exposed via `Deref`. A zero length range is represented without any
+/// mapping, as an empty slice.
+///
+/// This is typically obtained via [`crate::fs::ReadSlice::mem_map`] and
+/// requires the _mmap_ feature.
+pub struct MappedSlice {
+    map: Option<Mmap>,
+    offset: usize,
+    len: usize,
+}
+
+impl MappedSlice {
+    /// Map the `offset..offset+len` byte range of the file, read-only.
+    pub(crate) fn map(file: &File, offset: u64, len: u64)
+        -> io::Result<MappedSlice>
+    {
+        if len == 0 {
+            return Ok(MappedSlice { map: None, offset: 0, len: 0 });
+        }
+        let (aligned, delta, len) = aligned_range(offset, len)?;
+        let map = unsafe {
+            MmapOptions::new()
+                .offset(aligned)
+                .len(delta + len)
+                .map(file)?
+        };
+        Ok(MappedSlice { map: Some(map), offset: delta, len })
+    }
+}
+
+impl Deref for MappedSlice {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self.map {
+            Some(ref m) => &m[self.offset..(self.offset + self.len)],
+            None => &[],
+        }
+    }
+}
+
+impl fmt::Debug for MappedSlice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MappedSlice")
+            .field("ptr", &self.as_ptr())
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+/// A writable memory mapped region of a file, exposing exactly the
+/// requested byte range.
+///
+/// The mapping is either shared read-write, as obtained via
+/// [`crate::fs::ReadSlice::mem_map_mut`], where writes are carried through
+/// to the file, or private copy-on-write, as obtained via
+/// [`crate::fs::ReadSlice::mem_map_copy`], where writes are _not_ carried
+/// through to the file and are not visible to other mappings of the
+/// file. As with [`MappedSlice`], the page-aligned superset of the requested
+/// range is mapped, and a zero length range is represented without any
+/// mapping.
+///
+/// This requires the _mmap_ feature.
+pub struct MappedSliceMut {
+    map: Option<MmapMut>,
+    offset: usize,
+    len: usize,
+}
+
+impl MappedSliceMut {
+    /// Map the `offset..offset+len` byte range of the file, private and
+    /// copy-on-write.
+    pub(crate) fn map_copy(file: &File, offset: u64, len: u64)
+        -> io::Result<MappedSliceMut>
+    {
+        if len == 0 {
+            return Ok(MappedSliceMut { map: None, offset: 0, len: 0 });
+        }
+        let (aligned, delta, len) = aligned_range(offset, len)?;
+        let map = unsafe {
+            MmapOptions::new()
+                .offset(aligned)
+                .len(delta + len)
+                .map_copy(file)?
+        };
+        Ok(MappedSliceMut { map: Some(map), offset: delta, len })
+    }
+
+    /// Map the `offset..offset+len` byte range of the file, shared and
+    /// read-write. The file must be open for both reading and writing.
+    pub(crate) fn map_mut(file: &File, offset: u64, len: u64)
+        -> io::Result<MappedSliceMut>
+    {
+        if len == 0 {
+            return Ok(MappedSliceMut { map: None, offset: 0, len: 0 });
+        }
+        let (aligned, delta, len) = aligned_range(offset, len)?;
+        let map = unsafe {
+            MmapOptions::new()
+                .offset(aligned)
+                .len(delta + len)
+                .map_mut(file)?
+        };
+        Ok(MappedSliceMut { map: Some(map), offset: delta, len })
+    }
+
+    /// Flush outstanding modifications to the file (via `msync`), blocking
+    /// until complete. This has no effect for a copy-on-write mapping.
+    pub fn flush(&self) -> io::Result<()> {
+        self.flush_range(0, self.len)
+    }
+
+    /// Initiate flushing outstanding modifications to the file (via `msync`
+    /// with `MS_ASYNC`), without waiting for completion.
+    pub fn flush_async(&self) -> io::Result<()> {
+        match self.map {
+            Some(ref m) => m.flush_async_range(self.offset, self.len),
+            None => Ok(()),
+        }
+    }
+
+    /// Flush outstanding modifications in the given range, relative to the
+    /// start of this slice, blocking until complete. Other modifications may
+    /// also be flushed. Returns an error if the range is out of bounds.
+    pub fn flush_range(&self, offset: usize, len: usize) -> io::Result<()> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.len => {}
+            _ => return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "flush range is out of bounds"
+            )),
+        }
+        match self.map {
+            Some(ref m) if len > 0 => m.flush_range(self.offset + offset, len),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Deref for MappedSliceMut {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self.map {
+            Some(ref m) => &m[self.offset..(self.offset + self.len)],
+            None => &[],
+        }
+    }
+}
+
+impl DerefMut for MappedSliceMut {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        match self.map {
+            Some(ref mut m) => &mut m[self.offset..(self.offset + self.len)],
+            None => &mut [],
+        }
+    }
+}
+
+impl fmt::Debug for MappedSliceMut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MappedSliceMut")
+            .field("ptr", &self.as_ptr())
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+// Given a file offset and length, return the page-aligned file offset, the
+// delta from that to the requested offset, and the requested length as
+// usize. The delta plus length must fit in usize, or an error is returned.
+pub(crate) fn aligned_range(offset: u64, len: u64)
+    -> io::Result<(u64, usize, usize)>
+{
+    let delta = offset % (page_size() as u64);
+    match delta.checked_add(len) {
+        Some(total) if total <= usize::max_value() as u64 => {
+            Ok((offset - delta, delta as usize, len as usize))
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "memory map range exceeds addressable memory"
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tempfile::tempfile;
+
+    use super::*;
+
+    #[test]
+    fn test_aligned_range() {
+        let ps = page_size() as u64;
+        assert_eq!(aligned_range(0, 10).unwrap(), (0, 0, 10));
+        assert_eq!(aligned_range(ps + 3, 10).unwrap(), (ps, 3, 10));
+        assert!(aligned_range(3, u64::max_value()).is_err());
+        #[cfg(target_pointer_width = "32")]
+        assert!(aligned_range(0, 1u64 << 32).is_err());
+    }
+
+    #[test]
+    fn test_map_unaligned() {
+        let mut f = tempfile().unwrap();
+        let data: Vec<u8> = (0..(3 * page_size()))
+            .map(|i| (i % 251) as u8)
+            .collect();
+        f.write_all(&data).unwrap();
+
+        let start = page_size() + 7;
+        let m = MappedSlice::map(&f, start as u64, 5000).unwrap();
+        assert_eq!(m.len(), 5000);
+        assert_eq!(&m[..], &data[start..(start + 5000)]);
+    }
+
+    #[test]
+    fn test_map_empty() {
+        let f = tempfile().unwrap();
+        let m = MappedSlice::map(&f, 0, 0).unwrap();
+        assert!(m.is_empty());
+        let m = MappedSlice::map(&f, 12345, 0).unwrap();
+        assert!(m.is_empty());
+    }
+
+    #[test]
+    fn test_map_copy() {
+        let mut f = tempfile().unwrap();
+        f.write_all(b"0123456789").unwrap();
+
+        let mut m = MappedSliceMut::map_copy(&f, 3, 4).unwrap();
+        assert_eq!(&m[..], b"3456");
+        m[0] = b'x';
+        assert_eq!(&m[..], b"x456");
+        let r = MappedSlice::map(&f, 0, 10).unwrap();
+        assert_eq!(&r[..], b"0123456789");
+    }
+
+    #[test]
+    fn test_map_mut_flush() {
+        let mut f = tempfile().unwrap();
+        f.write_all(b"0123456789").unwrap();
+
+        let mut m = MappedSliceMut::map_mut(&f, 3, 4).unwrap();
+        m[..2].copy_from_slice(b"xy");
+        m.flush_range(0, 2).unwrap();
+        m.flush_async().unwrap();
+        m.flush().unwrap();
+        assert!(m.flush_range(3, 2).is_err());
+        assert!(m.flush_range(usize::max_value(), 2).is_err());
+        let r = MappedSlice::map(&f, 0, 10).unwrap();
+        assert_eq!(&r[..], b"012xy56789");
+
+        let m = MappedSliceMut::map_mut(&f, 3, 0).unwrap();
+        m.flush().unwrap();
+    }
+
+    #[test]
+    fn test_options_populate() {
+        let mut f = tempfile().unwrap();
+        f.write_all(&vec![3u8; 5 * page_size()]).unwrap();
+
+        let m = MappedSlice::map(&f, 11, 4 * page_size() as u64).unwrap();
+        MapOptions::new().populate().apply(&m).unwrap();
+        assert_eq!(m[0], 3u8);
+    }
+
+    #[test]
+    fn test_options_lock() {
+        let mut f = tempfile().unwrap();
+        f.write_all(&vec![3u8; 4 * page_size()]).unwrap();
+
+        let m = MappedSlice::map(&f, 0, 4 * page_size() as u64).unwrap();
+        match MapOptions::new().lock().apply(&m) {
+            Ok(()) => {}
+            Err(e) => {
+                // May fail, depending on RLIMIT_MEMLOCK
+                #[cfg(unix)]
+                assert!(e.raw_os_error() == Some(libc::ENOMEM)
+                        || e.raw_os_error() == Some(libc::EPERM)
+                        || e.raw_os_error() == Some(libc::EAGAIN));
+                #[cfg(not(unix))]
+                let _ = e;
+            }
+        }
+    }
+
+    #[test]
+    fn test_options_huge_pages() {
+        let mut f = tempfile().unwrap();
+        f.write_all(&vec![3u8; 4 * page_size()]).unwrap();
+
+        let m = MappedSlice::map(&f, 0, 4 * page_size() as u64).unwrap();
+        if let Err(e) = MapOptions::new().huge_pages().apply(&m) {
+            // Kernel may lack transparent huge page support
+            assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::ffi::CString;
+use std::fs::File;
+use std::io;
+use std::io::{Seek, SeekFrom, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+
+use crate::mem::{MappedSlice, MemHandle};
+
+// Seals required for a read-only, fixed size buffer.
+const SEALS: i32 =
+    libc::F_SEAL_WRITE | libc::F_SEAL_SHRINK | libc::F_SEAL_GROW;
+
+/// Writer for an anonymous, shared memory file (via `memfd_create`), which
+/// once written is sealed and mapped as a read-only `MemHandle`.
+///
+/// The sealed file may be passed to another process (e.g. over a unix
+/// socket), which can then map it via [`MemFdWriter::map_sealed`], without
+/// risk of it being modified or truncated.
+///
+/// This is Linux-only and requires the _mmap_ feature.
+#[derive(Debug)]
+pub struct MemFdWriter {
+    file: File,
+}
+
+impl MemFdWriter {
+    /// Create a new, empty memory file. The name is for debugging purposes
+    /// only, e.g. as shown in `/proc/self/fd`, and need not be unique.
+    pub fn new(name: &str) -> io::Result<MemFdWriter> {
+        let name = CString::new(name)?;
+        let flags = libc::MFD_CLOEXEC | libc::MFD_ALLOW_SEALING;
+        let fd = unsafe {
+            libc::syscall(libc::SYS_memfd_create, name.as_ptr(), flags)
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let file = unsafe { File::from_raw_fd(fd as RawFd) };
+        Ok(MemFdWriter { file })
+    }
+
+    /// Set the length of the memory file, truncating or zero extending as
+    /// needed.
+    pub fn set_len(&self, len: u64) -> io::Result<()> {
+        self.file.set_len(len)
+    }
+
+    /// Seal the memory file against any further writes or changes in
+    /// length, and map it read-only. Returns the `MemHandle` to the mapping
+    /// and the sealed file, which may be passed to other processes.
+    pub fn seal(self) -> io::Result<(MemHandle<MappedSlice>, File)> {
+        let seals = SEALS | libc::F_SEAL_SEAL;
+        let res = unsafe {
+            libc::fcntl(self.file.as_raw_fd(), libc::F_ADD_SEALS, seals)
+        };
+        if res != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let handle = MemFdWriter::map_sealed(&self.file)?;
+        Ok((handle, self.file))
+    }
+
+    /// Map a sealed memory file, for example as received from another
+    /// process, read-only. Returns an error if the file is not sealed
+    /// against writes and changes in length.
+    pub fn map_sealed(file: &File) -> io::Result<MemHandle<MappedSlice>> {
+        let seals = unsafe {
+            libc::fcntl(file.as_raw_fd(), libc::F_GET_SEALS)
+        };
+        if seals < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if seals & SEALS != SEALS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "memory file is not sealed against writes and resizing"
+            ));
+        }
+        let len = file.metadata()?.len();
+        Ok(MemHandle::new(MappedSlice::map(file, 0, len)?))
+    }
+}
+
+impl Write for MemFdWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for MemFdWriter {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.file.seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tempfile::tempfile;
+
+    use super::MemFdWriter;
+
+    #[test]
+    fn test_seal() {
+        let mut w = MemFdWriter::new("olio-test").unwrap();
+        w.write_all(b"hello sealed world").unwrap();
+        let (h, mut f) = w.seal().unwrap();
+        assert_eq!(&h[..], b"hello sealed world");
+
+        assert!(f.write_all(b"x").is_err());
+        assert!(f.set_len(5).is_err());
+
+        let h2 = MemFdWriter::map_sealed(&f).unwrap();
+        assert_eq!(&h2[..], &h[..]);
+    }
+
+    #[test]
+    fn test_seal_empty() {
+        let w = MemFdWriter::new("olio-empty").unwrap();
+        let (h, _f) = w.seal().unwrap();
+        assert!(h.is_empty());
+    }
+
+    #[test]
+    fn test_map_unsealed() {
+        let w = MemFdWriter::new("olio-unsealed").unwrap();
+        w.set_len(10).unwrap();
+        let e = MemFdWriter::map_sealed(&w.file).unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput);
+
+        let f = tempfile().unwrap();
+        assert!(MemFdWriter::map_sealed(&f).is_err());
+    }
+
+    #[test]
+    fn test_bad_name() {
+        assert!(MemFdWriter::new("a\0b").is_err());
+    }
+}
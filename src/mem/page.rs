@@ -0,0 +1,112 @@
+use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+
+// Cached page size, or 0 if not yet obtained.
+static PAGE_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+/// Return the operating system virtual memory page size in bytes.
+///
+/// On unix this is obtained once via `sysconf(_SC_PAGESIZE)` and cached. On
+/// other platforms, 4 KiB is assumed.
+pub fn page_size() -> usize {
+    let mut ps = PAGE_SIZE.load(Relaxed);
+    if ps == 0 {
+        ps = os_page_size();
+        PAGE_SIZE.store(ps, Relaxed);
+    }
+    ps
+}
+
+#[cfg(unix)]
+fn os_page_size() -> usize {
+    let ps = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    if ps > 0 { ps as usize } else { 4096 }
+}
+
+#[cfg(not(unix))]
+fn os_page_size() -> usize {
+    4096
+}
+
+// Return the page-aligned superset (start address, length) of the given
+// memory region.
+#[cfg(unix)]
+pub(crate) fn page_outer(addr: usize, len: usize) -> (usize, usize) {
+    let ps = page_size();
+    let start = addr - (addr % ps);
+    let end = addr + len;
+    let end = match end % ps {
+        0 => end,
+        r => end + (ps - r),
+    };
+    (start, end - start)
+}
+
+// Return the page-aligned subset (start address, length) of the given memory
+// region, containing only whole pages. The length may be 0.
+#[cfg(unix)]
+pub(crate) fn page_inner(addr: usize, len: usize) -> (usize, usize) {
+    let ps = page_size();
+    let start = match addr % ps {
+        0 => addr,
+        r => addr + (ps - r),
+    };
+    let end = addr + len;
+    let end = end - (end % ps);
+    if end > start { (start, end - start) } else { (start, 0) }
+}
+
+// Read a byte of each page of the given memory region, faulting in any
+// non-resident pages.
+pub(crate) fn touch(mem: &[u8]) {
+    if mem.is_empty() {
+        return;
+    }
+    let ps = page_size();
+    let first = match mem.as_ptr() as usize % ps {
+        0 => 0,
+        r => ps - r,
+    };
+    unsafe { std::ptr::read_volatile(&mem[0]); }
+    for i in (first..mem.len()).step_by(ps) {
+        unsafe { std::ptr::read_volatile(&mem[i]); }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_touch() {
+        touch(&[]);
+        touch(&vec![1u8; 3 * page_size() + 7]);
+    }
+
+    #[test]
+    fn test_page_size() {
+        let ps = page_size();
+        assert!(ps >= 4096);
+        assert!(ps.is_power_of_two());
+        assert_eq!(ps, page_size());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_page_outer() {
+        let ps = page_size();
+        assert_eq!(page_outer(ps, ps), (ps, ps));
+        assert_eq!(page_outer(ps + 1, 1), (ps, ps));
+        assert_eq!(page_outer(ps + 1, ps), (ps, 2 * ps));
+        assert_eq!(page_outer(ps - 1, 2), (0, 2 * ps));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_page_inner() {
+        let ps = page_size();
+        assert_eq!(page_inner(ps, ps), (ps, ps));
+        assert_eq!(page_inner(ps + 1, ps), (2 * ps, 0));
+        assert_eq!(page_inner(ps - 1, 2 * ps + 2), (ps, 2 * ps));
+        assert_eq!(page_inner(ps + 1, 2), (2 * ps, 0));
+    }
+}
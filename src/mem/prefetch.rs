@@ -0,0 +1,244 @@
+use std::collections::VecDeque;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+use std::sync::atomic::{
+    AtomicBool, AtomicPtr, AtomicUsize,
+    Ordering::{Acquire, Release},
+};
+use std::sync::{Arc, Condvar, Mutex, Once};
+use std::thread;
+
+use crate::mem::page::touch;
+
+// Bytes touched between progress updates and checks for cancellation.
+const CHUNK: usize = 256 * 1024;
+
+/// Handle to a background prefetch of memory, as returned by
+/// [`MemHandle::prefetch`](crate::mem::MemHandle::prefetch).
+///
+/// Cloning is inexpensive, and all clones refer to the same prefetch.
+/// Dropping does not cancel the prefetch.
+#[derive(Clone, Debug)]
+pub struct Prefetch {
+    state: Arc<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    total: usize,
+    done: AtomicUsize,
+    cancelled: AtomicBool,
+    finished: Mutex<bool>,
+    cond: Condvar,
+}
+
+impl Prefetch {
+    /// Return the total length of the region in bytes.
+    pub fn total(&self) -> usize {
+        self.state.total
+    }
+
+    /// Return the length in bytes of the region prefetched so far.
+    pub fn done(&self) -> usize {
+        self.state.done.load(Acquire)
+    }
+
+    /// Request cancellation. Prefetch stops shortly after, with the region
+    /// only partially prefetched.
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, Release);
+    }
+
+    /// Return true if cancellation was requested.
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Acquire)
+    }
+
+    /// Return true if the prefetch is complete, or has stopped after
+    /// cancellation.
+    pub fn is_finished(&self) -> bool {
+        *self.lock_finished()
+    }
+
+    /// Block until the prefetch is finished. Returns true if the entire
+    /// region was prefetched.
+    pub fn wait(&self) -> bool {
+        let mut finished = self.lock_finished();
+        while !*finished {
+            finished = self.state.cond.wait(finished)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+        self.done() == self.total()
+    }
+
+    fn lock_finished(&self) -> std::sync::MutexGuard<'_, bool> {
+        self.state.finished.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+// Start a prefetch (touching each page) of the given memory, on the shared
+// background thread, which keeps the memory alive via `owner`.
+pub(crate) fn spawn<O>(owner: O) -> Prefetch
+    where O: AsRef<[u8]> + Send + 'static
+{
+    let prefetch = Prefetch {
+        state: Arc::new(State {
+            total: owner.as_ref().len(),
+            done: AtomicUsize::new(0),
+            cancelled: AtomicBool::new(false),
+            finished: Mutex::new(false),
+            cond: Condvar::new(),
+        })
+    };
+    let p = prefetch.clone();
+    worker().submit(Box::new(move || run(owner, &p)));
+    prefetch
+}
+
+fn run<O>(owner: O, prefetch: &Prefetch)
+    where O: AsRef<[u8]>
+{
+    // Mark finished on return or panic, so waiters are never stranded
+    let _finish = Finish(prefetch);
+    let mut done = 0;
+    for chunk in owner.as_ref().chunks(CHUNK) {
+        if prefetch.is_cancelled() {
+            break;
+        }
+        touch(chunk);
+        done += chunk.len();
+        prefetch.state.done.store(done, Release);
+    }
+}
+
+struct Finish<'a>(&'a Prefetch);
+
+impl Drop for Finish<'_> {
+    fn drop(&mut self) {
+        *self.0.lock_finished() = true;
+        self.0.state.cond.notify_all();
+    }
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+// A single, lazily started background thread, shared by all prefetches and
+// running these in FIFO order.
+struct Worker {
+    queue: Mutex<VecDeque<Job>>,
+    cond: Condvar,
+    running: AtomicBool,
+}
+
+static WORKER: AtomicPtr<Worker> = AtomicPtr::new(ptr::null_mut());
+static WORKER_INIT: Once = Once::new();
+
+fn worker() -> &'static Worker {
+    WORKER_INIT.call_once(|| {
+        let w: &'static Worker = Box::leak(Box::new(Worker {
+            queue: Mutex::new(VecDeque::new()),
+            cond: Condvar::new(),
+            running: AtomicBool::new(false),
+        }));
+        let started = thread::Builder::new()
+            .name("olio-prefetch".to_owned())
+            .spawn(move || w.run())
+            .is_ok();
+        w.running.store(started, Release);
+        WORKER.store(w as *const Worker as *mut Worker, Release);
+    });
+    unsafe { &*WORKER.load(Acquire) }
+}
+
+impl Worker {
+    // Submit a job, or if the thread could not be started, run it in the
+    // calling thread.
+    fn submit(&self, job: Job) {
+        if self.running.load(Acquire) {
+            self.lock_queue().push_back(job);
+            self.cond.notify_one();
+        } else {
+            job();
+        }
+    }
+
+    fn run(&self) {
+        loop {
+            let job = {
+                let mut queue = self.lock_queue();
+                loop {
+                    match queue.pop_front() {
+                        Some(job) => break job,
+                        None => {
+                            queue = self.cond.wait(queue)
+                                .unwrap_or_else(|e| e.into_inner());
+                        }
+                    }
+                }
+            };
+            // Keep the thread alive for later jobs, if a job panics
+            catch_unwind(AssertUnwindSafe(job)).ok();
+        }
+    }
+
+    fn lock_queue(&self) -> std::sync::MutexGuard<'_, VecDeque<Job>> {
+        self.queue.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::mem::page_size;
+
+    #[test]
+    fn test_prefetch() {
+        let p = spawn(vec![1u8; 300 * page_size()]);
+        assert!(p.wait());
+        assert!(p.is_finished());
+        assert!(!p.is_cancelled());
+        assert_eq!(p.done(), p.total());
+    }
+
+    #[test]
+    fn test_prefetch_cancel() {
+        let p = spawn(vec![1u8; 4096 * page_size()]);
+        p.cancel();
+        p.wait();
+        assert!(p.is_finished());
+        assert!(p.is_cancelled());
+        assert!(p.done() <= p.total());
+    }
+
+    #[test]
+    fn test_prefetch_empty() {
+        let p = spawn(Vec::new());
+        assert!(p.wait());
+        assert_eq!(p.total(), 0);
+    }
+
+    struct PanicOnSecond(AtomicUsize);
+
+    impl AsRef<[u8]> for PanicOnSecond {
+        fn as_ref(&self) -> &[u8] {
+            static DATA: [u8; 4096] = [1u8; 4096];
+            if self.0.fetch_add(1, Release) > 0 {
+                panic!("PanicOnSecond");
+            }
+            &DATA
+        }
+    }
+
+    #[test]
+    fn test_prefetch_panic() {
+        let p = spawn(PanicOnSecond(AtomicUsize::new(0)));
+        assert!(!p.wait());
+        assert!(p.is_finished());
+        assert_eq!(p.total(), 4096);
+
+        // The worker survives for later prefetches
+        let p = spawn(vec![1u8; 4 * page_size()]);
+        assert!(p.wait());
+    }
+}
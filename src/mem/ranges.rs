@@ -0,0 +1,132 @@
+// A sorted list of non-overlapping `[start, end)` segments, each with a
+// non-default value. Gaps between segments implicitly have the default
+// value. This is used for tracking per-page state over a buffer, where the
+// number of distinct segments is expected to be small.
+#[derive(Debug, Default)]
+pub(crate) struct Segments<V> {
+    segs: Vec<(usize, usize, V)>,
+}
+
+impl<V> Segments<V>
+    where V: Copy + Default + PartialEq
+{
+    pub(crate) fn new() -> Segments<V> {
+        Segments { segs: Vec::new() }
+    }
+
+    // Return the non-default segments.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &(usize, usize, V)> {
+        self.segs.iter()
+    }
+
+    // Return a partition of `[start, end)` into pieces of the same value,
+    // including gaps with the default value.
+    pub(crate) fn pieces(&self, start: usize, end: usize)
+        -> Vec<(usize, usize, V)>
+    {
+        let mut out = Vec::new();
+        let mut pos = start;
+        for &(s, e, v) in &self.segs {
+            if e <= pos {
+                continue;
+            }
+            if s >= end {
+                break;
+            }
+            if s > pos {
+                out.push((pos, s, V::default()));
+                pos = s;
+            }
+            let e = e.min(end);
+            out.push((pos, e, v));
+            pos = e;
+        }
+        if pos < end {
+            out.push((pos, end, V::default()));
+        }
+        out
+    }
+
+    // Replace each value over `[start, end)`, including gaps, with the result
+    // of `f`. Returns the partition of `[start, end)` as changed, with old
+    // and new values.
+    pub(crate) fn update<F>(&mut self, start: usize, end: usize, mut f: F)
+        -> Vec<(usize, usize, V, V)>
+        where F: FnMut(V) -> V
+    {
+        let mut changes: Vec<(usize, usize, V, V)> = Vec::new();
+        for (s, e, old) in self.pieces(start, end) {
+            let new = f(old);
+            match changes.last_mut() {
+                Some(l) if l.2 == old && l.3 == new => l.1 = e,
+                _ => changes.push((s, e, old, new))
+            }
+        }
+
+        let mut segs = Vec::with_capacity(self.segs.len() + changes.len());
+        let mut tail = Vec::new();
+        for &(s, e, v) in &self.segs {
+            if e <= start {
+                segs.push((s, e, v));
+            } else if s >= end {
+                tail.push((s, e, v));
+            } else {
+                if s < start {
+                    segs.push((s, start, v));
+                }
+                if e > end {
+                    tail.push((end, e, v));
+                }
+            }
+        }
+        for &(s, e, _, v) in &changes {
+            if v != V::default() {
+                segs.push((s, e, v));
+            }
+        }
+        segs.extend(tail);
+
+        // Merge adjacent segments of equal value
+        self.segs.clear();
+        for (s, e, v) in segs {
+            match self.segs.last_mut() {
+                Some(l) if l.1 == s && l.2 == v => l.1 = e,
+                _ => self.segs.push((s, e, v))
+            }
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Segments;
+
+    #[test]
+    fn test_update_pieces() {
+        let mut segs = Segments::<u32>::new();
+        let ch = segs.update(10, 20, |v| v + 1);
+        assert_eq!(ch, vec![(10, 20, 0, 1)]);
+        let ch = segs.update(15, 30, |v| v + 1);
+        assert_eq!(ch, vec![(15, 20, 1, 2), (20, 30, 0, 1)]);
+        assert_eq!(segs.pieces(0, 40), vec![
+            (0, 10, 0), (10, 15, 1), (15, 20, 2), (20, 30, 1), (30, 40, 0)
+        ]);
+        segs.update(15, 20, |v| v - 1);
+        assert_eq!(segs.iter().cloned().collect::<Vec<_>>(),
+                   vec![(10, 30, 1)]);
+        segs.update(0, 40, |_| 0);
+        assert_eq!(segs.iter().count(), 0);
+    }
+
+    #[test]
+    fn test_update_inner() {
+        let mut segs = Segments::<u32>::new();
+        segs.update(0, 100, |_| 3);
+        let ch = segs.update(40, 60, |_| 0);
+        assert_eq!(ch, vec![(40, 60, 3, 0)]);
+        assert_eq!(segs.iter().cloned().collect::<Vec<_>>(),
+                   vec![(0, 40, 3), (60, 100, 3)]);
+        assert_eq!(segs.pieces(50, 70), vec![(50, 60, 0), (60, 70, 3)]);
+    }
+}
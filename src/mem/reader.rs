@@ -0,0 +1,226 @@
+use std::cmp;
+use std::io;
+use std::io::{BufRead, Read, Seek, SeekFrom};
+use std::ops::Deref;
+
+use crate::fs::{seek_offset, PosRead};
+use crate::mem::{MemAdvice, MemAdviseError, MemHandle};
+
+/// An owned `Read`, `BufRead`, `Seek` and `PosRead` cursor over a
+/// [`MemHandle`].
+///
+/// Unlike `std::io::Cursor` over a borrowed `&[u8]`, this owns its handle,
+/// so for a `Send + Sync` buffer (e.g. a memory map) it may be sent to
+/// another thread, while other handles to the same memory remain in use.
+/// `BufRead` is implemented without copying, as the remainder of the buffer
+/// is always available.
+#[derive(Debug)]
+pub struct MemReader<T>
+    where T: Deref<Target=[u8]>
+{
+    handle: MemHandle<T>,
+    pos: u64,
+}
+
+impl<T> MemReader<T>
+    where T: Deref<Target=[u8]>
+{
+    /// New reader over the given handle, positioned at the start.
+    pub fn new(handle: MemHandle<T>) -> MemReader<T> {
+        MemReader { handle, pos: 0 }
+    }
+
+    /// New reader over the given handle, positioned at the start, advising
+    /// `MemAdvice::Sequential` via the handle while the reader is active.
+    /// The advice is withdrawn when the reader is dropped, or may be
+    /// replaced via [`MemReader::get_ref`]. Returns an error if the advice
+    /// fails, subject to the [`AdvicePolicy`](crate::mem::AdvicePolicy).
+    pub fn sequential(handle: MemHandle<T>)
+        -> Result<MemReader<T>, MemAdviseError>
+    {
+        handle.advise(MemAdvice::Sequential)?;
+        Ok(MemReader::new(handle))
+    }
+
+    /// Return the length of the readable buffer in bytes.
+    pub fn len(&self) -> u64 {
+        self.handle.len() as u64
+    }
+
+    /// Return true if the readable buffer is empty.
+    pub fn is_empty(&self) -> bool {
+        self.handle.is_empty()
+    }
+
+    /// Return the current position, which may be beyond the end of the
+    /// buffer after a seek.
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// Return a reference to the inner handle.
+    pub fn get_ref(&self) -> &MemHandle<T> {
+        &self.handle
+    }
+
+    /// Unwrap the inner handle, retaining any advice given.
+    pub fn into_inner(self) -> MemHandle<T> {
+        self.handle
+    }
+
+    /// Return a new and independent `MemReader` for the range of byte
+    /// offsets `start..end`, which are relative to, and must be fully
+    /// contained by self, positioned at start. As with
+    /// [`MemHandle::slice`], the new reader's handle starts with the implicit
+    /// `MemAdvice::Normal`. This implementation _panics_ if start..end is
+    /// not fully contained, or if start is greater-than end.
+    pub fn subslice(&self, start: u64, end: u64) -> MemReader<T> {
+        assert!(start <= end);
+        assert!(end <= self.len());
+        MemReader::new(self.handle.slice((start as usize)..(end as usize)))
+    }
+
+    // Return the remainder of the buffer from the current position.
+    fn remaining(&self) -> &[u8] {
+        let start = cmp::min(self.pos, self.len()) as usize;
+        &self.handle[start..]
+    }
+}
+
+impl<T> PosRead for MemReader<T>
+    where T: Deref<Target=[u8]>
+{
+    fn pread(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        if offset >= self.len() {
+            return Ok(0);
+        }
+        let src = &self.handle[(offset as usize)..];
+        let n = cmp::min(buf.len(), src.len());
+        buf[..n].copy_from_slice(&src[..n]);
+        Ok(n)
+    }
+}
+
+impl<T> Read for MemReader<T>
+    where T: Deref<Target=[u8]>
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.pread(buf, self.pos)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<T> BufRead for MemReader<T>
+    where T: Deref<Target=[u8]>
+{
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        Ok(self.remaining())
+    }
+
+    fn consume(&mut self, amt: usize) {
+        let amt = cmp::min(amt, self.remaining().len());
+        self.pos += amt as u64;
+    }
+}
+
+impl<T> Seek for MemReader<T>
+    where T: Deref<Target=[u8]>
+{
+    fn seek(&mut self, from: SeekFrom) -> io::Result<u64> {
+        match from {
+            SeekFrom::Start(p) => {
+                self.pos = p;
+                Ok(p)
+            }
+            SeekFrom::End(offset) => {
+                let origin = self.len();
+                self.pos = seek_offset(origin, offset)?;
+                Ok(self.pos)
+            }
+            SeekFrom::Current(offset) => {
+                let origin = self.pos;
+                self.pos = seek_offset(origin, offset)?;
+                Ok(self.pos)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, Read, Seek, SeekFrom};
+    use std::thread;
+
+    use super::MemReader;
+    use crate::fs::PosRead;
+    use crate::mem::{MemAdvice, MemHandle};
+
+    #[test]
+    fn test_read_seek() {
+        let mut r = MemReader::new(MemHandle::new(b"0123456789".to_vec()));
+        let mut buf = [0u8; 4];
+        assert_eq!(r.read(&mut buf).unwrap(), 4);
+        assert_eq!(&buf, b"0123");
+        assert_eq!(r.seek(SeekFrom::End(-2)).unwrap(), 8);
+        assert_eq!(r.read(&mut buf).unwrap(), 2);
+        assert_eq!(&buf[..2], b"89");
+        assert_eq!(r.read(&mut buf).unwrap(), 0);
+        assert!(r.seek(SeekFrom::Current(-11)).is_err());
+        assert_eq!(r.seek(SeekFrom::Start(20)).unwrap(), 20);
+        assert_eq!(r.read(&mut buf).unwrap(), 0);
+        assert!(r.fill_buf().unwrap().is_empty());
+        assert_eq!(r.position(), 20);
+
+        assert_eq!(r.pread(&mut buf, 3).unwrap(), 4);
+        assert_eq!(&buf, b"3456");
+        assert_eq!(r.pread(&mut buf, 10).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_buf_read() {
+        let h = MemHandle::new(b"one\ntwo\nthree".to_vec());
+        let r = MemReader::new(h);
+        let lines: Vec<String> = r.lines().map(|l| l.unwrap()).collect();
+        assert_eq!(lines, vec!["one", "two", "three"]);
+
+        let mut r = MemReader::new(MemHandle::new(b"abc".to_vec()));
+        assert_eq!(r.fill_buf().unwrap(), b"abc");
+        r.consume(2);
+        assert_eq!(r.fill_buf().unwrap(), b"c");
+        r.consume(5);
+        assert!(r.fill_buf().unwrap().is_empty());
+        assert_eq!(r.position(), 3);
+    }
+
+    #[test]
+    fn test_subslice() {
+        let r = MemReader::new(MemHandle::new(b"0123456789".to_vec()));
+        let mut s = r.subslice(2, 8);
+        assert_eq!(s.len(), 6);
+        let mut s2 = s.subslice(1, 3);
+        let mut out = String::new();
+        s.read_to_string(&mut out).unwrap();
+        assert_eq!(out, "234567");
+        assert_eq!(s2.fill_buf().unwrap(), b"34");
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_subslice_bounds() {
+        let r = MemReader::new(MemHandle::new(vec![0u8; 10]));
+        r.subslice(5, 11);
+    }
+
+    #[test]
+    fn test_sequential() {
+        let h = MemHandle::new(vec![3u8; 8192]);
+        let r = MemReader::sequential(h.clone()).unwrap();
+        assert_eq!(h.advice_snapshot().advisors(MemAdvice::Sequential), 1);
+        let t = thread::spawn(move || {
+            r.bytes().map(|b| b.unwrap() as usize).sum::<usize>()
+        });
+        assert_eq!(t.join().unwrap(), 3 * 8192);
+        assert_eq!(h.advice_snapshot().advisors(MemAdvice::Sequential), 0);
+    }
+}
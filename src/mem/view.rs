@@ -0,0 +1,321 @@
+use std::fmt;
+use std::io;
+use std::mem::{align_of, size_of};
+use std::ops::Deref;
+use std::ptr;
+use std::slice;
+
+use crate::mem::MemHandle;
+
+/// Marker trait for plain-old-data types, which may be safely viewed from
+/// arbitrary bytes, via [`MemHandle::as_slice_of`], [`MemHandle::view_at`]
+/// and [`MemHandle::read_at`].
+///
+/// This is implemented for the primitive integer and floating point types,
+/// and for the endian-explicit integer wrappers of this module, such as
+/// [`U32Le`]. For records stored in a fixed byte order, prefer the wrappers,
+/// which have an alignment of 1 and are thus never misaligned.
+///
+/// # Safety
+///
+/// Implementing types must be `#[repr(C)]` or `#[repr(transparent)]` (or
+/// primitive), have no padding bytes, contain no references or pointers, and
+/// be valid for any bit pattern. For example, `bool`, `char` and enums are
+/// _not_ valid.
+pub unsafe trait Pod: Copy + 'static {}
+
+macro_rules! pod {
+    ($($t:ty),*) => { $(unsafe impl Pod for $t {})* }
+}
+
+pod!(u8, i8, u16, i16, u32, i32, u64, i64, u128, i128, usize, isize,
+     f32, f64);
+
+macro_rules! endian {
+    ($name:ident, $int:ty, $n:expr, $to:ident, $from:ident, $doc:literal) => {
+        #[doc = $doc]
+        #[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+        #[repr(transparent)]
+        pub struct $name([u8; $n]);
+
+        impl $name {
+            /// New instance, storing the value in this byte order.
+            pub fn new(v: $int) -> $name {
+                $name(v.$to())
+            }
+
+            /// Return the value, converted to native byte order.
+            pub fn get(self) -> $int {
+                <$int>::$from(self.0)
+            }
+        }
+
+        impl From<$int> for $name {
+            fn from(v: $int) -> $name {
+                $name::new(v)
+            }
+        }
+
+        impl From<$name> for $int {
+            fn from(v: $name) -> $int {
+                v.get()
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_tuple(stringify!($name)).field(&self.get()).finish()
+            }
+        }
+
+        unsafe impl Pod for $name {}
+    }
+}
+
+endian!(U16Le, u16, 2, to_le_bytes, from_le_bytes,
+        "A little-endian `u16`, with alignment 1.");
+endian!(U16Be, u16, 2, to_be_bytes, from_be_bytes,
+        "A big-endian `u16`, with alignment 1.");
+endian!(U32Le, u32, 4, to_le_bytes, from_le_bytes,
+        "A little-endian `u32`, with alignment 1.");
+endian!(U32Be, u32, 4, to_be_bytes, from_be_bytes,
+        "A big-endian `u32`, with alignment 1.");
+endian!(U64Le, u64, 8, to_le_bytes, from_le_bytes,
+        "A little-endian `u64`, with alignment 1.");
+endian!(U64Be, u64, 8, to_be_bytes, from_be_bytes,
+        "A big-endian `u64`, with alignment 1.");
+endian!(I16Le, i16, 2, to_le_bytes, from_le_bytes,
+        "A little-endian `i16`, with alignment 1.");
+endian!(I16Be, i16, 2, to_be_bytes, from_be_bytes,
+        "A big-endian `i16`, with alignment 1.");
+endian!(I32Le, i32, 4, to_le_bytes, from_le_bytes,
+        "A little-endian `i32`, with alignment 1.");
+endian!(I32Be, i32, 4, to_be_bytes, from_be_bytes,
+        "A big-endian `i32`, with alignment 1.");
+endian!(I64Le, i64, 8, to_le_bytes, from_le_bytes,
+        "A little-endian `i64`, with alignment 1.");
+endian!(I64Be, i64, 8, to_be_bytes, from_be_bytes,
+        "A big-endian `i64`, with alignment 1.");
+
+/// Error viewing bytes as a [`Pod`] type, for misaligned or truncated
+/// data.
+///
+/// Implements `std::error::Error` and may be converted to an
+/// `io::Error(InvalidData)`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ViewError {
+    kind: Kind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Kind {
+    Misaligned { offset: usize, align: usize },
+    Truncated { offset: usize, size: usize, len: usize },
+    ZeroSized,
+}
+
+impl ViewError {
+    /// Return true if the data is not aligned as required for the type.
+    pub fn is_misaligned(&self) -> bool {
+        match self.kind {
+            Kind::Misaligned { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Return true if the data is too short for the type, or for a slice,
+    /// has a trailing partial element.
+    pub fn is_truncated(&self) -> bool {
+        match self.kind {
+            Kind::Truncated { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Return the byte offset of the misaligned or truncated element, if
+    /// applicable.
+    pub fn offset(&self) -> Option<usize> {
+        match self.kind {
+            Kind::Misaligned { offset, .. } => Some(offset),
+            Kind::Truncated { offset, .. } => Some(offset),
+            Kind::ZeroSized => None,
+        }
+    }
+}
+
+impl fmt::Display for ViewError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            Kind::Misaligned { offset, align } => {
+                write!(f, "data at offset {} is not aligned to {} bytes",
+                       offset, align)
+            }
+            Kind::Truncated { offset, size, len } => {
+                write!(f, "{} bytes at offset {} exceeds length {}",
+                       size, offset, len)
+            }
+            Kind::ZeroSized => {
+                write!(f, "can't view data as a zero sized type")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ViewError {}
+
+impl From<ViewError> for io::Error {
+    fn from(ve: ViewError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, ve)
+    }
+}
+
+impl<T> MemHandle<T>
+    where T: Deref<Target=[u8]>
+{
+    /// Return this handle's buffer as a slice of a [`Pod`] type. Returns an
+    /// error if the buffer is not aligned for the type, or its length is not
+    /// a multiple of the type's size. An empty buffer is always viewed as an
+    /// empty slice.
+    pub fn as_slice_of<V: Pod>(&self) -> Result<&[V], ViewError> {
+        let size = checked_size::<V>()?;
+        if self.is_empty() {
+            return Ok(&[]);
+        }
+        let rem = self.len() % size;
+        if rem != 0 {
+            return Err(ViewError { kind: Kind::Truncated {
+                offset: self.len() - rem, size, len: self.len()
+            }});
+        }
+        check_align::<V>(self, 0)?;
+        Ok(unsafe {
+            slice::from_raw_parts(self.as_ptr() as *const V, self.len() / size)
+        })
+    }
+
+    /// Return a reference to a [`Pod`] type at the given byte offset of this
+    /// handle's buffer. Returns an error if the data is misaligned for the
+    /// type, or extends beyond the end of the buffer. (This is not named
+    /// `get`, to avoid shadowing `[u8]::get` via `Deref`.)
+    pub fn view_at<V: Pod>(&self, offset: usize) -> Result<&V, ViewError> {
+        check_bounds::<V>(self, offset)?;
+        check_align::<V>(self, offset)?;
+        Ok(unsafe { &*(self[offset..].as_ptr() as *const V) })
+    }
+
+    /// Return a copy of a [`Pod`] type read from the given byte offset of
+    /// this handle's buffer, which need not be aligned. Returns an error if
+    /// the data extends beyond the end of the buffer.
+    pub fn read_at<V: Pod>(&self, offset: usize) -> Result<V, ViewError> {
+        check_bounds::<V>(self, offset)?;
+        Ok(unsafe {
+            ptr::read_unaligned(self[offset..].as_ptr() as *const V)
+        })
+    }
+}
+
+fn checked_size<V: Pod>() -> Result<usize, ViewError> {
+    match size_of::<V>() {
+        0 => Err(ViewError { kind: Kind::ZeroSized }),
+        size => Ok(size),
+    }
+}
+
+fn check_bounds<V: Pod>(mem: &[u8], offset: usize) -> Result<(), ViewError> {
+    let size = checked_size::<V>()?;
+    match offset.checked_add(size) {
+        Some(end) if end <= mem.len() => Ok(()),
+        _ => Err(ViewError { kind: Kind::Truncated {
+            offset, size, len: mem.len()
+        }}),
+    }
+}
+
+fn check_align<V: Pod>(mem: &[u8], offset: usize) -> Result<(), ViewError> {
+    let align = align_of::<V>();
+    if (mem.as_ptr() as usize + offset) % align == 0 {
+        Ok(())
+    } else {
+        Err(ViewError { kind: Kind::Misaligned { offset, align } })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::{I16Be, Pod, U16Be, U32Le, U64Le};
+    use crate::mem::{AlignedBuf, MemHandle};
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    #[repr(C)]
+    struct Record {
+        id: U32Le,
+        kind: U16Be,
+        delta: I16Be,
+        size: U64Le,
+    }
+
+    unsafe impl Pod for Record {}
+
+    fn record_bytes(id: u32, size: u64) -> Vec<u8> {
+        let mut b = Vec::new();
+        b.extend_from_slice(&id.to_le_bytes());
+        b.extend_from_slice(&7u16.to_be_bytes());
+        b.extend_from_slice(&(-2i16).to_be_bytes());
+        b.extend_from_slice(&size.to_le_bytes());
+        b
+    }
+
+    #[test]
+    fn test_endian() {
+        let v = U32Le::new(0x0102_0304);
+        assert_eq!(v.get(), 0x0102_0304);
+        assert_eq!(u32::from(v), 0x0102_0304);
+        assert_eq!(U16Be::from(0x0102).get(), 0x0102);
+        assert_eq!(format!("{:?}", I16Be::new(-5)), "I16Be(-5)");
+        assert_eq!(std::mem::align_of::<U64Le>(), 1);
+    }
+
+    #[test]
+    fn test_records() {
+        let mut bytes = record_bytes(1, 100);
+        bytes.extend(record_bytes(2, 200));
+        let h = MemHandle::new(bytes);
+        let recs = h.as_slice_of::<Record>().unwrap();
+        assert_eq!(recs.len(), 2);
+        assert_eq!(recs[1].id.get(), 2);
+        assert_eq!(recs[1].size.get(), 200);
+        assert_eq!(recs[0].kind.get(), 7);
+        assert_eq!(recs[0].delta.get(), -2);
+        assert_eq!(h.view_at::<Record>(16).unwrap(), &recs[1]);
+        assert_eq!(h.view_at::<U64Le>(8).unwrap().get(), 100);
+
+        let e = h.slice(0..20).as_slice_of::<Record>().unwrap_err();
+        assert!(e.is_truncated());
+        assert_eq!(e.offset(), Some(16));
+        let e = h.view_at::<Record>(17).unwrap_err();
+        assert!(e.is_truncated());
+        assert!(h.view_at::<Record>(usize::max_value()).is_err());
+        assert_eq!(io::Error::from(e).kind(), io::ErrorKind::InvalidData);
+        assert!(h.slice(0..0).as_slice_of::<Record>().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_aligned() {
+        let mut buf = AlignedBuf::new(64);
+        buf[8..16].copy_from_slice(&42u64.to_ne_bytes());
+        let h = MemHandle::new(buf);
+        assert_eq!(h.as_slice_of::<u64>().unwrap()[1], 42);
+        assert_eq!(*h.view_at::<u64>(8).unwrap(), 42);
+
+        let s = h.slice(1..33);
+        let e = s.as_slice_of::<u64>().unwrap_err();
+        assert!(e.is_misaligned());
+        assert_eq!(e.offset(), Some(0));
+        assert!(s.view_at::<u32>(3).is_ok());
+        assert!(s.view_at::<u32>(4).unwrap_err().is_misaligned());
+        assert_eq!(s.read_at::<u64>(7).unwrap(), 42);
+        assert!(s.read_at::<u64>(25).unwrap_err().is_truncated());
+    }
+}